@@ -0,0 +1,239 @@
+// xid与verify共用的时间处理, 时间戳均为UTC纳秒
+
+// 2020-01-01T00:00:00Z, 新签名的消息不早于此时间
+pub const MIN_TIMESTAMP : u64 = 1_577_836_800_000_000_000;
+// 2006-03-21T00:00:00Z, Twitter上线, 推文发布时间不早于此时间
+pub const TWITTER_EPOCH : u64 = 1_142_899_200_000_000_000;
+// 允许客户端时钟超前5分钟
+pub const MAX_CLOCK_SKEW : u64 = 5 * 60 * 1_000_000_000;
+
+// 校验新签名消息的时间
+pub fn is_valid(t : u64, now : u64) -> bool {
+    is_valid_since(t, MIN_TIMESTAMP, now)
+}
+
+// 不早于floor且不晚于当前时间(含时钟偏差), 历史数据按所属平台的起始时间校验
+pub fn is_valid_since(t : u64, floor : u64, now : u64) -> bool {
+    t >= floor && t <= now.saturating_add(MAX_CLOCK_SKEW)
+}
+
+// 按数量级推断单位并换算为纳秒: 秒/毫秒/微秒/纳秒, 换算溢出时为None
+pub fn normalize(raw : u64) -> Option<u64> {
+    if raw < 100_000_000_000 {
+        raw.checked_mul(1_000_000_000)
+    } else if raw < 100_000_000_000_000 {
+        raw.checked_mul(1_000_000)
+    } else if raw < 100_000_000_000_000_000 {
+        raw.checked_mul(1_000)
+    } else {
+        Some(raw)
+    }
+}
+
+// 数字或RFC 3339字符串, 无法解析或超出u64纳秒范围时为None
+pub fn parse(s : &str) -> Option<u64> {
+    let s = s.trim();
+    if !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit()) {
+        return s.parse::<u64>().ok().and_then(normalize);
+    };
+    parse_rfc3339(s)
+}
+
+fn parse_rfc3339(s : &str) -> Option<u64> {
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':'
+        || !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    };
+    let num = |from : usize, to : usize| -> Option<u64> {
+        let part = s.get(from..to)?;
+        if part.bytes().all(|c| c.is_ascii_digit()) { part.parse().ok() } else { None }
+    };
+    let (year, month, day) = (num(0, 4)?, num(5, 7)?, num(8, 10)?);
+    let (hour, min, sec) = (num(11, 13)?, num(14, 16)?, num(17, 19)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day)
+        || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    };
+    let mut rest = s.get(19..)?;
+    let mut nanos = 0;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let len = frac.bytes().take_while(|c| c.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        };
+        let digits = &frac[..len.min(9)];
+        nanos = digits.parse::<u64>().ok()? * 10u64.pow(9 - digits.len() as u32);
+        rest = &frac[len..];
+    };
+    let offset : i64 = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            if rest.len() != 6 || rest.as_bytes()[3] != b':' {
+                return None;
+            };
+            let h = rest.get(1..3)?.parse::<i64>().ok()?;
+            let m = rest.get(4..6)?.parse::<i64>().ok()?;
+            sign * (h * 3600 + m * 60)
+        },
+    };
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    let secs = days * 86400 + (hour * 3600 + min * 60 + sec) as i64 - offset;
+    if secs < 0 {
+        return None;
+    };
+    (secs as u64).checked_mul(1_000_000_000)?.checked_add(nanos)
+}
+
+// 纳秒时间戳格式化为 YYYY-MM-DD (UTC), 用于页面展示
+pub fn format_date(t : u64) -> String {
+    let (y, m, d) = civil_from_days((t / 1_000_000_000 / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+const WEEKDAYS : [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS : [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                             "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// RFC 3339 (UTC, 精确到秒), 用于Atom
+pub fn format_rfc3339(t : u64) -> String {
+    let secs = t / 1_000_000_000;
+    let (y, m, d) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, m, d, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+// HTTP日期 (RFC 7231 IMF-fixdate), 同时用于RSS的pubDate
+pub fn format_http_date(t : u64) -> String {
+    let secs = t / 1_000_000_000;
+    let days = (secs / 86400) as i64;
+    let (y, m, d) = civil_from_days(days);
+    let rem = secs % 86400;
+    format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[((days + 4) % 7) as usize], d, MONTHS[(m - 1) as usize], y,
+            rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+// 解析If-Modified-Since等请求头中的IMF-fixdate, 返回纳秒
+pub fn parse_http_date(s : &str) -> Option<u64> {
+    let parts : Vec<&str> = s.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        return None;
+    };
+    let day = parts[1].parse::<i64>().ok()?;
+    let month = MONTHS.iter().position(|m| *m == parts[2])? as i64 + 1;
+    let year = parts[3].parse::<i64>().ok()?;
+    let time : Vec<i64> = parts[4].split(':').filter_map(|p| p.parse::<u8>().ok()).map(i64::from).collect();
+    if !(1970..=9999).contains(&year) || !(1..=31).contains(&day) || time.len() != 3
+        || time[0] > 23 || time[1] > 59 || time[2] > 60 {
+        return None;
+    };
+    let secs = days_from_civil(year, month, day) * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    (secs as u64).checked_mul(1_000_000_000)
+}
+
+// 公历日期距1970-01-01的天数
+fn days_from_civil(y : i64, m : i64, d : i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// days_from_civil的逆运算
+fn civil_from_days(z : i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-11-14T22:13:20Z
+    const T : u64 = 1_700_000_000_000_000_000;
+
+    #[test]
+    fn normalize_infers_unit() {
+        assert_eq!(normalize(1_700_000_000), Some(T));
+        assert_eq!(normalize(1_700_000_000_000), Some(T));
+        assert_eq!(normalize(1_700_000_000_000_000), Some(T));
+        assert_eq!(normalize(T), Some(T));
+    }
+
+    #[test]
+    fn normalize_rejects_overflow() {
+        assert_eq!(normalize(18_446_744_073), Some(18_446_744_073_000_000_000));
+        assert_eq!(normalize(18_446_744_074), None);
+        assert_eq!(normalize(99_999_999_999), None);
+        assert_eq!(normalize(18_446_744_073_709), Some(18_446_744_073_709_000_000));
+        assert_eq!(normalize(18_446_744_073_710), None);
+        assert_eq!(normalize(99_999_999_999_999_999), None);
+        assert_eq!(normalize(u64::MAX), Some(u64::MAX));
+    }
+
+    #[test]
+    fn parse_numbers_and_rfc3339() {
+        assert_eq!(parse(" 1700000000 "), Some(T));
+        assert_eq!(parse("99999999999"), None);
+        assert_eq!(parse("99999999999999999999"), None);
+        assert_eq!(parse("2023-11-14T22:13:20Z"), Some(T));
+        assert_eq!(parse("2023-11-14t22:13:20.5z"), Some(T + 500_000_000));
+        assert_eq!(parse("2023-11-15T06:13:20+08:00"), Some(T));
+        assert_eq!(parse("2023-11-14T21:13:20.123456789123-01:00"), Some(T + 123_456_789));
+        assert_eq!(parse("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse("2023-13-14T22:13:20Z"), None);
+        assert_eq!(parse("2023-11-14T22:13:20"), None);
+        assert_eq!(parse("2023-11-14T22:13:20.Z"), None);
+        assert_eq!(parse("2023-11-14T22:13:20+0800"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn is_valid_window() {
+        assert!(is_valid(MIN_TIMESTAMP, T));
+        assert!(!is_valid(MIN_TIMESTAMP - 1, T));
+        assert!(is_valid(T + MAX_CLOCK_SKEW, T));
+        assert!(!is_valid(T + MAX_CLOCK_SKEW + 1, T));
+        assert!(is_valid(u64::MAX, u64::MAX));
+        assert!(!is_valid(0, T));
+        assert!(is_valid_since(TWITTER_EPOCH, TWITTER_EPOCH, T));
+        assert!(!is_valid_since(TWITTER_EPOCH - 1, TWITTER_EPOCH, T));
+        assert!(is_valid_since(0, 0, T));
+        assert!(!is_valid_since(T + MAX_CLOCK_SKEW + 1, 0, T));
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(format_date(T), "2023-11-14");
+        assert_eq!(format_rfc3339(T + 999_999_999), "2023-11-14T22:13:20Z");
+        assert_eq!(format_http_date(T), "Tue, 14 Nov 2023 22:13:20 GMT");
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_date(951_782_400_000_000_000), "2000-02-29");
+    }
+
+    #[test]
+    fn http_date_round_trip() {
+        for t in [0, T, 951_782_400_000_000_000, 4_102_444_799_000_000_000] {
+            assert_eq!(parse_http_date(&format_http_date(t)), Some(t));
+        }
+        assert_eq!(parse_http_date("Tue, 14 Nov 2023 22:13:20 UTC"), None);
+        assert_eq!(parse_http_date("Tue, 14 Foo 2023 22:13:20 GMT"), None);
+        assert_eq!(parse_http_date("Tue, 14 Nov 2023 24:13:20 GMT"), None);
+        assert_eq!(parse_http_date("Tue, 14 Nov 9223372036854775807 22:13:20 GMT"), None);
+    }
+}
@@ -0,0 +1,714 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use sha2::{Digest, Sha224, Sha256};
+use crate::VerifyError;
+
+// 主网根公钥 (DER), 本地replica部署时通过init参数替换
+pub const IC_ROOT_KEY : &[u8; 133] = b"\x30\x81\x82\x30\x1d\x06\x0d\x2b\x06\x01\x04\x01\x82\xdc\x7c\x05\x03\x01\x02\x01\x06\x0c\x2b\x06\x01\x04\x01\x82\xdc\x7c\x05\x03\x02\x01\x03\x61\x00\x81\x4c\x0e\x6e\xc7\x1f\xab\x58\x3b\x08\xbd\x81\x37\x3c\x25\x5c\x3c\x37\x1b\x2e\x84\x86\x3c\x98\xa4\xf1\xe0\x8b\x74\x23\x5d\x14\xfb\x5d\x9c\x0c\xd5\x46\xd9\x68\x5f\x91\x3a\x0c\x0b\x2c\xc5\x34\x15\x83\xbf\x4b\x43\x92\xe4\x67\xdb\x96\xd6\x5b\x9b\xb4\xcb\x71\x71\x12\xf8\x47\x2e\x0d\x5a\x4d\x14\x50\x5f\xfd\x74\x84\xb0\x12\x91\x09\x1c\x5f\x87\xb9\x88\x83\x46\x3f\x98\x09\x1a\x0b\xaa\xae";
+
+// 委托链长度上限, 限制单次调用的验签开销; II签发的链长度为1
+pub const MAX_DELEGATIONS : usize = 4;
+const BLS_KEY_SIZE : usize = 96;
+const OID_ED25519 : &[u8] = &[0x2b, 0x65, 0x70];
+const OID_EC_PUBLIC_KEY : &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256 : &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_SECP256K1 : &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x0a];
+const OID_CANISTER_SIG : &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xb8, 0x43, 0x01, 0x02];
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct Delegation {
+    pub pubkey : Vec<u8>,
+    pub expiration : u64,
+    pub targets : Option<Vec<Principal>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct SignedDelegation {
+    pub delegation : Delegation,
+    pub signature : Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct DelegationIn {
+    pub public_key : Vec<u8>, // 根身份公钥 (DER)
+    pub delegations : Vec<SignedDelegation>,
+    pub message : Vec<u8>,
+    pub signature : Vec<u8>, // 最后一级委托密钥对message的签名
+}
+
+struct PublicKeyInfo<'a> {
+    alg : &'a [u8],
+    params : Option<&'a [u8]>,
+    key : &'a [u8],
+}
+
+enum HashTree {
+    Empty,
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned([u8; 32]),
+}
+
+// 校验委托链, 成功返回根公钥对应的principal
+pub fn verify_delegation(arg : &DelegationIn, target : Principal, now : u64, root_key : &[u8]) -> Result<Principal, VerifyError> {
+    if arg.delegations.len() > MAX_DELEGATIONS {
+        return Err(VerifyError::DelegationErr);
+    };
+    let mut current_key = arg.public_key.clone();
+    for signed in arg.delegations.iter() {
+        let d = &signed.delegation;
+        if d.expiration < now {
+            return Err(VerifyError::DelegationExpired);
+        };
+        if let Some(targets) = &d.targets {
+            if !targets.contains(&target) {
+                return Err(VerifyError::DelegationErr);
+            };
+        };
+        let mut msg = domain_sep("ic-request-auth-delegation");
+        msg.extend_from_slice(&delegation_hash(d));
+        if !verify_signature(&current_key, &msg, &signed.signature, root_key) {
+            return Err(VerifyError::VerifyErr);
+        };
+        current_key = d.pubkey.clone();
+    }
+    if !verify_signature(&current_key, &arg.message, &arg.signature, root_key) {
+        return Err(VerifyError::VerifyErr);
+    };
+    Ok(self_authenticating(&arg.public_key))
+}
+
+fn self_authenticating(public_key : &[u8]) -> Principal {
+    let mut bytes = Sha224::digest(public_key).to_vec();
+    bytes.push(0x02);
+    Principal::from_slice(&bytes)
+}
+
+fn verify_signature(der_key : &[u8], msg : &[u8], sig : &[u8], root_key : &[u8]) -> bool {
+    let PublicKeyInfo { alg, params, key } = match parse_spki(der_key) {
+        Some(res) => res,
+        None => return false,
+    };
+    if alg == OID_ED25519 {
+        let pk = match ed25519_compact::PublicKey::from_slice(key) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let sig = match ed25519_compact::Signature::from_slice(sig) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        pk.verify(msg, &sig).is_ok()
+    } else if alg == OID_EC_PUBLIC_KEY && params == Some(OID_P256) {
+        use p256::ecdsa::signature::Verifier;
+        let pk = match p256::ecdsa::VerifyingKey::from_sec1_bytes(key) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let sig = match p256::ecdsa::Signature::from_slice(sig) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        pk.verify(msg, &sig).is_ok()
+    } else if alg == OID_EC_PUBLIC_KEY && params == Some(OID_SECP256K1) {
+        let pk = match secp256k1::PublicKey::parse_slice(key, None) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let sig = match secp256k1::Signature::parse_standard_slice(sig) {
+            Ok(res) => res,
+            Err(_) => return false,
+        };
+        let hash : [u8; 32] = Sha256::digest(msg).into();
+        secp256k1::verify(&secp256k1::Message::parse(&hash), &sig, &pk)
+    } else if alg == OID_CANISTER_SIG {
+        verify_canister_sig(key, msg, sig, root_key)
+    } else {
+        false
+    }
+}
+
+// canister签名: 证书中certified_data须覆盖 sig/<hash(seed)>/<hash(msg)>
+fn verify_canister_sig(key : &[u8], msg : &[u8], sig : &[u8], root_key : &[u8]) -> bool {
+    if key.is_empty() || key.len() < 1 + key[0] as usize {
+        return false;
+    };
+    let canister_id = &key[1..1 + key[0] as usize];
+    let seed = &key[1 + key[0] as usize..];
+    let sig = match serde_cbor::from_slice::<Value>(sig) {
+        Ok(res) => res,
+        Err(_) => return false,
+    };
+    let certificate = match map_get(&sig, "certificate").and_then(as_bytes) {
+        Some(res) => res,
+        None => return false,
+    };
+    let sig_tree = match map_get(&sig, "tree").and_then(parse_tree) {
+        Some(res) => res,
+        None => return false,
+    };
+    let cert_tree = match verify_certificate(&certificate, canister_id, root_key) {
+        Some(res) => res,
+        None => return false,
+    };
+    let certified_data = lookup(&cert_tree, &[b"canister", canister_id, b"certified_data"]);
+    if certified_data != Some(&tree_hash(&sig_tree)[..]) {
+        return false;
+    };
+    let seed_hash = Sha256::digest(seed);
+    let msg_hash = Sha256::digest(msg);
+    lookup(&sig_tree, &[b"sig", &seed_hash, &msg_hash]).is_some()
+}
+
+// 校验证书BLS签名(含子网委托), 返回证书中的状态树
+fn verify_certificate(certificate : &[u8], canister_id : &[u8], root_key : &[u8]) -> Option<HashTree> {
+    let cert = serde_cbor::from_slice::<Value>(certificate).ok()?;
+    let tree = parse_tree(map_get(&cert, "tree")?)?;
+    let signature = as_bytes(map_get(&cert, "signature")?)?;
+    let key = match map_get(&cert, "delegation") {
+        None => bls_key(root_key)?,
+        Some(delegation) => {
+            let subnet_id = as_bytes(map_get(delegation, "subnet_id")?)?;
+            let inner = as_bytes(map_get(delegation, "certificate")?)?;
+            let inner_cert = serde_cbor::from_slice::<Value>(&inner).ok()?;
+            if map_get(&inner_cert, "delegation").is_some() {
+                return None;
+            };
+            let inner_tree = verify_certificate(&inner, canister_id, root_key)?;
+            let ranges = lookup(&inner_tree, &[b"subnet", &subnet_id, b"canister_ranges"])?;
+            if !in_ranges(ranges, canister_id) {
+                return None;
+            };
+            bls_key(lookup(&inner_tree, &[b"subnet", &subnet_id, b"public_key"])?)?
+        },
+    };
+    let mut msg = domain_sep("ic-state-root");
+    msg.extend_from_slice(&tree_hash(&tree));
+    ic_verify_bls_signature::verify_bls_signature(&signature, &msg, &key).ok()?;
+    Some(tree)
+}
+
+fn bls_key(der_key : &[u8]) -> Option<Vec<u8>> {
+    let key = parse_spki(der_key)?.key;
+    if key.len() != BLS_KEY_SIZE {
+        return None;
+    };
+    Some(key.to_vec())
+}
+
+fn in_ranges(ranges : &[u8], canister_id : &[u8]) -> bool {
+    let ranges = match serde_cbor::from_slice::<Value>(ranges) {
+        Ok(Value::Array(res)) => res,
+        _ => return false,
+    };
+    ranges.iter().any(|range| match range {
+        Value::Array(r) if r.len() == 2 => match (as_bytes(&r[0]), as_bytes(&r[1])) {
+            (Some(lo), Some(hi)) => lo.as_slice() <= canister_id && canister_id <= hi.as_slice(),
+            _ => false,
+        },
+        _ => false,
+    })
+}
+
+// 解析DER编码的SubjectPublicKeyInfo
+fn parse_spki(der : &[u8]) -> Option<PublicKeyInfo<'_>> {
+    let (tag, spki, _) = der_next(der)?;
+    if tag != 0x30 {
+        return None;
+    };
+    let (tag, alg, rest) = der_next(spki)?;
+    if tag != 0x30 {
+        return None;
+    };
+    let (tag, oid, alg_rest) = der_next(alg)?;
+    if tag != 0x06 {
+        return None;
+    };
+    let params = match der_next(alg_rest) {
+        Some((0x06, p, _)) => Some(p),
+        _ => None,
+    };
+    let (tag, bits, _) = der_next(rest)?;
+    if tag != 0x03 || bits.is_empty() || bits[0] != 0 {
+        return None;
+    };
+    Some(PublicKeyInfo { alg: oid, params, key: &bits[1..] })
+}
+
+// 读取一个DER元素: (tag, 内容, 剩余)
+fn der_next(data : &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    if data.len() < 2 {
+        return None;
+    };
+    let tag = data[0];
+    let (len, header) = if data[1] < 0x80 {
+        (data[1] as usize, 2)
+    } else {
+        let n = (data[1] & 0x7f) as usize;
+        if n == 0 || n > 4 || data.len() < 2 + n {
+            return None;
+        };
+        let len = data[2..2 + n].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + n)
+    };
+    if data.len() < header + len {
+        return None;
+    };
+    Some((tag, &data[header..header + len], &data[header + len..]))
+}
+
+// representation-independent hash
+fn delegation_hash(d : &Delegation) -> [u8; 32] {
+    let mut fields : Vec<Vec<u8>> = Vec::new();
+    fields.push([Sha256::digest(b"pubkey"), Sha256::digest(&d.pubkey)].concat());
+    fields.push([Sha256::digest(b"expiration"), Sha256::digest(leb128(d.expiration))].concat());
+    if let Some(targets) = &d.targets {
+        let mut hasher = Sha256::new();
+        for t in targets {
+            hasher.update(Sha256::digest(t.as_slice()));
+        }
+        fields.push([Sha256::digest(b"targets"), hasher.finalize()].concat());
+    };
+    fields.sort();
+    Sha256::digest(fields.concat()).into()
+}
+
+fn leb128(mut n : u64) -> Vec<u8> {
+    let mut res = Vec::new();
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            res.push(byte);
+            return res;
+        };
+        res.push(byte | 0x80);
+    }
+}
+
+fn domain_sep(domain : &str) -> Vec<u8> {
+    let mut res = vec![domain.len() as u8];
+    res.extend_from_slice(domain.as_bytes());
+    res
+}
+
+fn tree_hash(tree : &HashTree) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    match tree {
+        HashTree::Empty => {
+            hasher.update(domain_sep("ic-hashtree-empty"));
+        },
+        HashTree::Fork(l, r) => {
+            hasher.update(domain_sep("ic-hashtree-fork"));
+            hasher.update(tree_hash(l));
+            hasher.update(tree_hash(r));
+        },
+        HashTree::Labeled(label, sub) => {
+            hasher.update(domain_sep("ic-hashtree-labeled"));
+            hasher.update(label);
+            hasher.update(tree_hash(sub));
+        },
+        HashTree::Leaf(data) => {
+            hasher.update(domain_sep("ic-hashtree-leaf"));
+            hasher.update(data);
+        },
+        HashTree::Pruned(hash) => return *hash,
+    };
+    hasher.finalize().into()
+}
+
+fn lookup<'a>(tree : &'a HashTree, path : &[&[u8]]) -> Option<&'a [u8]> {
+    if path.is_empty() {
+        return match tree {
+            HashTree::Leaf(data) => Some(data),
+            _ => None,
+        };
+    };
+    match tree {
+        HashTree::Fork(l, r) => lookup(l, path).or_else(|| lookup(r, path)),
+        HashTree::Labeled(label, sub) if label.as_slice() == path[0] => lookup(sub, &path[1..]),
+        _ => None,
+    }
+}
+
+fn parse_tree(value : &Value) -> Option<HashTree> {
+    let items = match value {
+        Value::Array(items) if !items.is_empty() => items,
+        _ => return None,
+    };
+    let tag = match items[0] {
+        Value::Integer(t) => t,
+        _ => return None,
+    };
+    match (tag, items.len()) {
+        (0, 1) => Some(HashTree::Empty),
+        (1, 3) => Some(HashTree::Fork(Box::new(parse_tree(&items[1])?), Box::new(parse_tree(&items[2])?))),
+        (2, 3) => Some(HashTree::Labeled(as_bytes(&items[1])?, Box::new(parse_tree(&items[2])?))),
+        (3, 2) => Some(HashTree::Leaf(as_bytes(&items[1])?)),
+        (4, 2) => Some(HashTree::Pruned(as_bytes(&items[1])?.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn map_get<'a>(value : &'a Value, key : &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(m) => m.get(&Value::Text(key.to_string())),
+        Value::Tag(_, inner) => map_get(inner, key),
+        _ => None,
+    }
+}
+
+fn as_bytes(value : &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Bytes(b) => Some(b.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_compact::{KeyPair, Seed};
+
+    const NOW : u64 = 1_700_000_000_000_000_000;
+
+    fn key_pair(n : u8) -> KeyPair {
+        KeyPair::from_seed(Seed::new([n; 32]))
+    }
+
+    fn der(key : &KeyPair) -> Vec<u8> {
+        let mut der = vec![0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+        der.extend_from_slice(key.pk.as_ref());
+        der
+    }
+
+    fn sign_delegation(signer : &KeyPair, delegation : Delegation) -> SignedDelegation {
+        let mut msg = domain_sep("ic-request-auth-delegation");
+        msg.extend_from_slice(&delegation_hash(&delegation));
+        SignedDelegation { signature: signer.sk.sign(&msg, None).to_vec(), delegation }
+    }
+
+    // root -> session, session签名message
+    fn chain(targets : Option<Vec<Principal>>, expiration : u64) -> DelegationIn {
+        let (root, session) = (key_pair(1), key_pair(2));
+        let message = b"xid-ic-verify:nonce".to_vec();
+        DelegationIn {
+            public_key: der(&root),
+            delegations: vec![sign_delegation(&root, Delegation { pubkey: der(&session), expiration, targets })],
+            signature: session.sk.sign(&message, None).to_vec(),
+            message,
+        }
+    }
+
+    fn target() -> Principal {
+        Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap()
+    }
+
+    fn verify(arg : &DelegationIn) -> Result<Principal, VerifyError> {
+        verify_delegation(arg, target(), NOW, IC_ROOT_KEY)
+    }
+
+    #[test]
+    fn valid_chain_returns_root_principal() {
+        let arg = chain(None, NOW);
+        let principal = verify(&arg).unwrap();
+        assert_eq!(principal, self_authenticating(&arg.public_key));
+        assert_eq!(principal.as_slice().len(), 29);
+        assert_eq!(principal.as_slice()[28], 0x02);
+        assert!(verify(&chain(Some(vec![Principal::anonymous(), target()]), NOW)).is_ok());
+    }
+
+    #[test]
+    fn root_key_may_sign_directly() {
+        let root = key_pair(1);
+        let message = b"hello".to_vec();
+        let arg = DelegationIn {
+            public_key: der(&root),
+            delegations: vec![],
+            signature: root.sk.sign(&message, None).to_vec(),
+            message,
+        };
+        assert!(verify(&arg).is_ok());
+    }
+
+    #[test]
+    fn expired_or_untargeted_delegations_are_rejected() {
+        assert!(matches!(verify(&chain(None, NOW - 1)), Err(VerifyError::DelegationExpired)));
+        assert!(matches!(verify(&chain(Some(vec![Principal::anonymous()]), NOW)), Err(VerifyError::DelegationErr)));
+        assert!(matches!(verify(&chain(Some(vec![]), NOW)), Err(VerifyError::DelegationErr)));
+    }
+
+    #[test]
+    fn tampered_chain_is_rejected() {
+        let mut arg = chain(None, NOW);
+        arg.delegations[0].delegation.expiration += 1;
+        assert!(matches!(verify(&arg), Err(VerifyError::VerifyErr)));
+
+        let mut arg = chain(None, NOW);
+        arg.message.push(0);
+        assert!(matches!(verify(&arg), Err(VerifyError::VerifyErr)));
+
+        // 委托链须从声明的根公钥开始
+        let mut arg = chain(None, NOW);
+        arg.public_key = der(&key_pair(3));
+        assert!(matches!(verify(&arg), Err(VerifyError::VerifyErr)));
+
+        let mut arg = chain(None, NOW);
+        arg.public_key.truncate(10);
+        assert!(matches!(verify(&arg), Err(VerifyError::VerifyErr)));
+    }
+
+    #[test]
+    fn hashing_helpers() {
+        assert_eq!(leb128(0), vec![0]);
+        assert_eq!(leb128(127), vec![0x7f]);
+        assert_eq!(leb128(128), vec![0x80, 0x01]);
+        assert_eq!(leb128(624_485), vec![0xe5, 0x8e, 0x26]);
+        assert_eq!(domain_sep("ic-state-root")[0], 13);
+
+        let d = Delegation { pubkey: vec![1], expiration: 1, targets: None };
+        let with_targets = Delegation { targets: Some(vec![]), ..d.clone() };
+        assert_ne!(delegation_hash(&d), delegation_hash(&with_targets));
+    }
+
+    #[test]
+    fn root_key_is_bls() {
+        let info = parse_spki(IC_ROOT_KEY).unwrap();
+        assert_eq!(info.key.len(), BLS_KEY_SIZE);
+        assert!(bls_key(IC_ROOT_KEY).is_some());
+        assert!(bls_key(&der(&key_pair(1))).is_none());
+        assert!(der_next(&[0x30, 0x82, 0x01]).is_none());
+    }
+
+    #[test]
+    fn tree_lookup() {
+        let tree = HashTree::Fork(
+            Box::new(HashTree::Labeled(b"a".to_vec(), Box::new(HashTree::Leaf(b"1".to_vec())))),
+            Box::new(HashTree::Labeled(b"b".to_vec(), Box::new(HashTree::Labeled(
+                b"c".to_vec(), Box::new(HashTree::Leaf(b"2".to_vec())))))),
+        );
+        assert_eq!(lookup(&tree, &[b"a"]), Some(&b"1"[..]));
+        assert_eq!(lookup(&tree, &[b"b", b"c"]), Some(&b"2"[..]));
+        assert_eq!(lookup(&tree, &[b"b"]), None);
+        assert_eq!(lookup(&tree, &[b"d"]), None);
+
+        let pruned = HashTree::Fork(
+            Box::new(HashTree::Pruned(tree_hash(&HashTree::Labeled(b"a".to_vec(), Box::new(HashTree::Leaf(b"1".to_vec())))))),
+            Box::new(HashTree::Labeled(b"b".to_vec(), Box::new(HashTree::Labeled(
+                b"c".to_vec(), Box::new(HashTree::Leaf(b"2".to_vec())))))),
+        );
+        assert_eq!(tree_hash(&tree), tree_hash(&pruned));
+    }
+
+    #[test]
+    fn chain_length_is_capped() {
+        let keys : Vec<KeyPair> = (1..=MAX_DELEGATIONS as u8 + 2).map(key_pair).collect();
+        let build = |len : usize| {
+            let message = b"hello".to_vec();
+            DelegationIn {
+                public_key: der(&keys[0]),
+                delegations: (0..len).map(|i| sign_delegation(&keys[i], Delegation {
+                    pubkey: der(&keys[i + 1]),
+                    expiration: NOW,
+                    targets: None,
+                })).collect(),
+                signature: keys[len].sk.sign(&message, None).to_vec(),
+                message,
+            }
+        };
+        assert!(verify(&build(MAX_DELEGATIONS)).is_ok());
+        assert!(matches!(verify(&build(MAX_DELEGATIONS + 1)), Err(VerifyError::DelegationErr)));
+    }
+
+    // 以下为II使用的canister签名路径: 用测试BLS根密钥签发证书(含子网委托), 证书覆盖签名树
+    mod canister_sig {
+        use super::*;
+        use ic_verify_bls_signature::PrivateKey;
+
+        const SEED : &[u8] = b"anchor-10000";
+
+        fn ii() -> Principal {
+            Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap()
+        }
+
+        fn bls_private_key(n : u8) -> PrivateKey {
+            PrivateKey::deserialize(&[n; 32]).unwrap()
+        }
+
+        // 与IC_ROOT_KEY相同的DER头部
+        fn bls_der(key : &PrivateKey) -> Vec<u8> {
+            let mut der = IC_ROOT_KEY[..IC_ROOT_KEY.len() - BLS_KEY_SIZE].to_vec();
+            der.extend_from_slice(&key.public_key().serialize());
+            der
+        }
+
+        fn canister_sig_der(canister : &Principal, seed : &[u8]) -> Vec<u8> {
+            let mut key = vec![0x00, canister.as_slice().len() as u8];
+            key.extend_from_slice(canister.as_slice());
+            key.extend_from_slice(seed);
+            let mut alg = vec![0x06, OID_CANISTER_SIG.len() as u8];
+            alg.extend_from_slice(OID_CANISTER_SIG);
+            let mut content = vec![0x30, alg.len() as u8];
+            content.extend(alg);
+            content.extend([0x03, key.len() as u8]);
+            content.extend(key);
+            let mut der = vec![0x30, content.len() as u8];
+            der.extend(content);
+            der
+        }
+
+        fn int(n : i128) -> Value {
+            Value::Integer(n)
+        }
+
+        fn bytes(b : &[u8]) -> Value {
+            Value::Bytes(b.to_vec())
+        }
+
+        fn labeled(label : &[u8], sub : Value) -> Value {
+            Value::Array(vec![int(2), bytes(label), sub])
+        }
+
+        fn leaf(data : &[u8]) -> Value {
+            Value::Array(vec![int(3), bytes(data)])
+        }
+
+        fn fork(l : Value, r : Value) -> Value {
+            Value::Array(vec![int(1), l, r])
+        }
+
+        fn map(entries : Vec<(&str, Value)>) -> Value {
+            Value::Map(entries.into_iter().map(|(k, v)| (Value::Text(k.to_string()), v)).collect())
+        }
+
+        fn hash_of(tree : &Value) -> [u8; 32] {
+            tree_hash(&parse_tree(tree).unwrap())
+        }
+
+        fn certificate(tree : Value, signer : &PrivateKey, delegation : Option<Value>) -> Vec<u8> {
+            let mut msg = domain_sep("ic-state-root");
+            msg.extend_from_slice(&hash_of(&tree));
+            let mut entries = vec![("tree", tree), ("signature", bytes(&signer.sign(&msg).serialize()))];
+            if let Some(d) = delegation {
+                entries.push(("delegation", d));
+            };
+            serde_cbor::to_vec(&map(entries)).unwrap()
+        }
+
+        struct Fixture {
+            root : PrivateKey,
+            subnet : PrivateKey,
+            canister : Principal, // 证书中认证的canister
+            range : (Principal, Principal), // 子网的canister范围
+            subnet_delegation : bool,
+        }
+
+        impl Fixture {
+            fn new() -> Self {
+                Fixture {
+                    root: bls_private_key(1),
+                    subnet: bls_private_key(2),
+                    canister: ii(),
+                    range: (Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 1]), ii()),
+                    subnet_delegation: true,
+                }
+            }
+
+            fn sig_tree(message : &[u8]) -> Value {
+                let seed_hash = Sha256::digest(SEED);
+                let msg_hash = Sha256::digest(message);
+                labeled(b"sig", labeled(&seed_hash, labeled(&msg_hash, leaf(b""))))
+            }
+
+            // II对message的canister签名: CBOR {certificate, tree}
+            fn sign(&self, sig_tree : Value) -> Vec<u8> {
+                let state = labeled(b"canister", labeled(self.canister.as_slice(),
+                    labeled(b"certified_data", leaf(&hash_of(&sig_tree)))));
+                let certificate = if self.subnet_delegation {
+                    let subnet_id = b"subnet-id";
+                    let ranges = serde_cbor::to_vec(&Value::Array(vec![Value::Array(vec![
+                        bytes(self.range.0.as_slice()), bytes(self.range.1.as_slice()),
+                    ])])).unwrap();
+                    let subnet_tree = labeled(b"subnet", labeled(subnet_id, fork(
+                        labeled(b"canister_ranges", leaf(&ranges)),
+                        labeled(b"public_key", leaf(&bls_der(&self.subnet))),
+                    )));
+                    let delegation = map(vec![
+                        ("subnet_id", bytes(subnet_id)),
+                        ("certificate", bytes(&certificate(subnet_tree, &self.root, None))),
+                    ]);
+                    certificate(state, &self.subnet, Some(delegation))
+                } else {
+                    certificate(state, &self.root, None)
+                };
+                let sig = map(vec![("certificate", bytes(&certificate)), ("tree", sig_tree)]);
+                serde_cbor::to_vec(&Value::Tag(55799, Box::new(sig))).unwrap()
+            }
+
+            // II根密钥(canister签名) -> 会话密钥 -> message
+            fn chain(&self, sig_tree : impl Fn(&[u8]) -> Value) -> DelegationIn {
+                let session = key_pair(2);
+                let delegation = Delegation { pubkey: der(&session), expiration: NOW, targets: None };
+                let mut msg = domain_sep("ic-request-auth-delegation");
+                msg.extend_from_slice(&delegation_hash(&delegation));
+                let message = b"xid-ic-verify:nonce".to_vec();
+                DelegationIn {
+                    public_key: canister_sig_der(&ii(), SEED),
+                    delegations: vec![SignedDelegation { signature: self.sign(sig_tree(&msg)), delegation }],
+                    signature: session.sk.sign(&message, None).to_vec(),
+                    message,
+                }
+            }
+
+            fn verify(&self, arg : &DelegationIn) -> Result<Principal, VerifyError> {
+                verify_delegation(arg, target(), NOW, &bls_der(&self.root))
+            }
+        }
+
+        #[test]
+        fn internet_identity_chain() {
+            let fixture = Fixture::new();
+            let arg = fixture.chain(Fixture::sig_tree);
+            assert_eq!(fixture.verify(&arg).unwrap(), self_authenticating(&canister_sig_der(&ii(), SEED)));
+            let direct = Fixture { subnet_delegation: false, ..Fixture::new() };
+            assert!(direct.verify(&direct.chain(Fixture::sig_tree)).is_ok());
+            // 主网根密钥不认可测试密钥签发的证书
+            assert!(verify_delegation(&arg, target(), NOW, IC_ROOT_KEY).is_err());
+        }
+
+        #[test]
+        fn wrong_canister_is_rejected() {
+            // 证书认证的是其他canister的certified_data
+            let other = Fixture { canister: target(), range: (ii(), target()), ..Fixture::new() };
+            assert!(matches!(other.verify(&other.chain(Fixture::sig_tree)), Err(VerifyError::VerifyErr)));
+            // II不在子网的canister范围内
+            let out_of_range = Fixture { range: (target(), target()), ..Fixture::new() };
+            assert!(matches!(out_of_range.verify(&out_of_range.chain(Fixture::sig_tree)), Err(VerifyError::VerifyErr)));
+        }
+
+        #[test]
+        fn tampered_witness_is_rejected() {
+            let fixture = Fixture::new();
+            // 签名树覆盖的是其他消息
+            let arg = fixture.chain(|_| Fixture::sig_tree(b"other message"));
+            assert!(matches!(fixture.verify(&arg), Err(VerifyError::VerifyErr)));
+            // 证书签发后替换签名树中的叶子
+            let mut arg = fixture.chain(Fixture::sig_tree);
+            let sig = serde_cbor::from_slice::<Value>(&arg.delegations[0].signature).unwrap();
+            let certificate = map_get(&sig, "certificate").and_then(as_bytes).unwrap();
+            let tree = fork(Fixture::sig_tree(&arg.message), leaf(b"extra"));
+            arg.delegations[0].signature = serde_cbor::to_vec(&map(vec![
+                ("certificate", bytes(&certificate)),
+                ("tree", tree),
+            ])).unwrap();
+            assert!(matches!(fixture.verify(&arg), Err(VerifyError::VerifyErr)));
+            // 篡改签名字节
+            let mut arg = fixture.chain(Fixture::sig_tree);
+            let last = arg.delegations[0].signature.len() - 1;
+            arg.delegations[0].signature[last] ^= 1;
+            assert!(fixture.verify(&arg).is_err());
+        }
+    }
+}
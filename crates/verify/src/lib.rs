@@ -1,15 +1,19 @@
+pub mod delegation;
+pub mod timestamp;
+
 use std::cell::RefCell;
 use secp256k1::{Message, PublicKey, RecoveryId, Signature, PublicKeyFormat};
 use secp256k1::util::{MESSAGE_SIZE, SIGNATURE_SIZE};
 use secp256k1::util::{FULL_PUBLIC_KEY_SIZE, RAW_PUBLIC_KEY_SIZE, COMPRESSED_PUBLIC_KEY_SIZE};
 use sha3::{Digest, Keccak256};
-use candid::{CandidType, candid_method};
-use ic_cdk_macros::{query, pre_upgrade, post_upgrade};
+use candid::{CandidType, candid_method, Principal};
+use ic_cdk_macros::{init, query, pre_upgrade, post_upgrade};
 use ic_cdk;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use base64;
 use std::collections::{BTreeSet};
+use delegation::{DelegationIn, IC_ROOT_KEY};
 
 thread_local! {
     static STATE : State = State::default();
@@ -20,11 +24,13 @@ pub const RECOVERY_ID_SIZE: usize = 1;
 #[derive(Default, Deserialize, Serialize, CandidType, Clone)]
 pub struct State {
     pub uuids : RefCell<BTreeSet<String>>,
+    pub ic_root_key : RefCell<Vec<u8>>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct StableState {
     pub uuids : BTreeSet<String>,
+    pub ic_root_key : Option<Vec<u8>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
@@ -37,6 +43,9 @@ pub enum VerifyError {
     XidNotExist,
     XidCNoNameErr,
     ReplayErr,
+    DelegationErr,
+    DelegationExpired,
+    TimestampErr,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
@@ -49,7 +58,8 @@ pub struct Verification {
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType, Default)]
 pub struct Payload {
     pub action : String,  // 行为
-    pub created_at : String, // 创建时间
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub created_at : u64, // 创建时间, ns
     pub identity : String, // 地址 推特Id
     pub persona : String, // 待删除
     pub platform : String, // 平台
@@ -62,6 +72,15 @@ pub struct MsgIn {
     pub sig : String,
 }
 
+// 本地replica部署时传入其根公钥 (DER), 默认主网
+#[init]
+#[candid_method(init)]
+fn init(root_key : Option<Vec<u8>>) {
+    STATE.with(|s| {
+        *s.ic_root_key.borrow_mut() = root_key.unwrap_or_else(|| IC_ROOT_KEY.to_vec());
+    })
+}
+
 #[query(name = "msg_in")]
 #[candid_method(query, rename = "msg_in")]
 pub fn msg_in(msgin : MsgIn) -> Result<Payload, VerifyError> {
@@ -69,6 +88,9 @@ pub fn msg_in(msgin : MsgIn) -> Result<Payload, VerifyError> {
         Ok(tmp) => tmp,
         Err(_) => return Err(VerifyError::MsgDecodeErr)
     };
+    if !timestamp::is_valid(res.created_at, ic_cdk::api::time()) {
+        return Err(VerifyError::TimestampErr);
+    };
     let mut flag = false;
     STATE.with(|s| {
         let mut uuids = s.uuids.borrow_mut();
@@ -96,6 +118,19 @@ pub fn msg_in(msgin : MsgIn) -> Result<Payload, VerifyError> {
     }
 }
 
+// 校验Internet Identity委托链, 返回被委托的principal
+#[query(name = "delegation_in")]
+#[candid_method(query, rename = "delegation_in")]
+pub fn delegation_in(arg : DelegationIn) -> Result<Principal, VerifyError> {
+    let root_key = STATE.with(|s| s.ic_root_key.borrow().clone());
+    delegation::verify_delegation(&arg, ic_cdk::caller(), ic_cdk::api::time(), &root_key)
+}
+
+#[query(name = "getRootKey")]
+#[candid_method(query, rename = "getRootKey")]
+fn get_root_key() -> Vec<u8> {
+    STATE.with(|s| s.ic_root_key.borrow().clone())
+}
 
 fn verify(verification: Verification) -> bool {
     if verification.message.len() != MESSAGE_SIZE { return false };
@@ -152,6 +187,7 @@ fn hash_keccak256(payload: String) -> [u8; 32] {
 fn do_clear() {
     STATE.with(|s| {
         s.uuids.borrow_mut().clear();
+        s.ic_root_key.borrow_mut().clear();
     })
 }
 
@@ -159,6 +195,7 @@ fn do_clear() {
 fn pre_upgrade() {
     let stable_state : StableState = STATE.with(|s| StableState{
         uuids: s.uuids.take(),
+        ic_root_key: Some(s.ic_root_key.take()),
     });
     ic_cdk::storage::stable_save((stable_state, )).expect("failed to save stable state");
 }
@@ -171,5 +208,6 @@ fn post_upgrade() {
 
     STATE.with(|s| {
         s.uuids.replace(stable_state.uuids);
+        s.ic_root_key.replace(stable_state.ic_root_key.unwrap_or_else(|| IC_ROOT_KEY.to_vec()));
     })
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+use std::fmt;
+use serde::{de, Deserializer};
+
+pub use xid_time::is_valid;
+use xid_time::{normalize, parse};
+
+// 客户端消息中的created_at可为数字或字符串, 统一换算为纳秒;
+// 换算溢出时为0, 由is_valid拒绝并返回TimestampErr
+pub fn deserialize<'de, D : Deserializer<'de>>(deserializer : D) -> Result<u64, D::Error> {
+    struct TimestampVisitor;
+    impl de::Visitor<'_> for TimestampVisitor {
+        type Value = u64;
+        fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a unix timestamp or an RFC 3339 date")
+        }
+        fn visit_u64<E : de::Error>(self, v : u64) -> Result<u64, E> {
+            Ok(normalize(v).unwrap_or(0))
+        }
+        fn visit_str<E : de::Error>(self, v : &str) -> Result<u64, E> {
+            parse(v).ok_or_else(|| E::custom("invalid timestamp"))
+        }
+    }
+    deserializer.deserialize_any(TimestampVisitor)
+}
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use askama::{Html, MarkupDisplay};
+use candid::Principal;
+use serde_json::{json, Value};
+use crate::credential;
+use crate::disclosure::{public_ids, public_main_id};
+use crate::pages::{avatar_url, safe_url, truncate};
+use crate::feed::MAX_TITLE_LEN;
+use crate::timestamp;
+use crate::types::{ContentType, Contents, State, Storage};
+
+// 只读联邦: 提供WebFinger, Person, outbox与各条内容的对象文档, 不处理inbox投递
+pub const WEBFINGER_PATH : &str = "/.well-known/webfinger";
+pub const ACTOR_PATH : &str = "/actor";
+pub const OUTBOX_PATH : &str = "/outbox";
+pub const FOLLOWERS_PATH : &str = "/followers";
+pub const FOLLOWING_PATH : &str = "/following";
+pub const INBOX_PATH : &str = "/inbox";
+pub const OBJECTS_PREFIX : &str = "/objects/";
+pub const ACTIVITY_TYPE : &str = "application/activity+json; charset=utf-8";
+pub const JRD_TYPE : &str = "application/jrd+json; charset=utf-8";
+pub const OUTBOX_ITEMS : usize = 20;
+// 超过此长度的链下内容作为Article
+pub const NOTE_MAX_LEN : usize = 500;
+// 每个xid独占一个canister域名, 用户名固定, 修改name不影响已有的关注与提及
+pub const USERNAME : &str = "xid";
+
+const AS_CONTEXT : &str = "https://www.w3.org/ns/activitystreams";
+const SECURITY_CONTEXT : &str = "https://w3id.org/security/v1";
+
+fn host(base : &str) -> &str {
+    base.trim_start_matches("https://")
+}
+
+// WebFinger的resource可为acct:用户名@域名, actor地址或主页地址
+pub fn webfinger_matches(base : &str, resource : &str) -> bool {
+    let subject = format!("acct:{}@{}", USERNAME, host(base));
+    resource.eq_ignore_ascii_case(&subject)
+        || resource == format!("{}{}", base, ACTOR_PATH)
+        || resource.trim_end_matches('/') == base
+}
+
+pub fn render_webfinger(base : &str) -> Vec<u8> {
+    let actor = format!("{}{}", base, ACTOR_PATH);
+    json!({
+        "subject": format!("acct:{}@{}", USERNAME, host(base)),
+        "aliases": [actor, format!("{}/", base)],
+        "links": [
+            { "rel": "self", "type": "application/activity+json", "href": actor },
+            { "rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": format!("{}/", base) },
+        ],
+    }).to_string().into_bytes()
+}
+
+// Person: 公开的绑定身份作为PropertyValue附件展示;
+// 公钥为canister签名公钥(credential::public_key), 只读联邦不对投递签名
+pub fn render_actor(s : &State, base : &str, canister : Principal) -> Vec<u8> {
+    let actor_id = format!("{}{}", base, ACTOR_PATH);
+    let name = s.name.borrow();
+    let summary = match public_main_id(s) {
+        Some(main_id) => format!("<p>{}: {}</p>", escape(&main_id.platform), escape(&main_id.identity)),
+        None => String::new(),
+    };
+    let attachment : Vec<Value> = public_ids(s).iter().map(|id| json!({
+        "type": "PropertyValue",
+        "name": id.platform,
+        "value": escape(&id.identity),
+    })).collect();
+    let mut actor = json!({
+        "@context": [AS_CONTEXT, SECURITY_CONTEXT, {
+            "schema": "http://schema.org#",
+            "PropertyValue": "schema:PropertyValue",
+            "value": "schema:value",
+        }],
+        "id": actor_id,
+        "type": "Person",
+        "preferredUsername": USERNAME,
+        "name": if name.is_empty() { "xid" } else { name.as_str() },
+        "summary": summary,
+        "url": format!("{}/", base),
+        "inbox": format!("{}{}", base, INBOX_PATH),
+        "outbox": format!("{}{}", base, OUTBOX_PATH),
+        "followers": format!("{}{}", base, FOLLOWERS_PATH),
+        "following": format!("{}{}", base, FOLLOWING_PATH),
+        "manuallyApprovesFollowers": true,
+        "discoverable": true,
+        "attachment": attachment,
+        "publicKey": {
+            "id": format!("{}#main-key", actor_id),
+            "owner": actor_id,
+            "publicKeyPem": public_key_pem(canister),
+        },
+    });
+    if let Some(icon) = avatar_url(s, base) {
+        actor["icon"] = json!({ "type": "Image", "url": icon });
+    };
+    actor.to_string().into_bytes()
+}
+
+fn public_key_pem(canister : Principal) -> String {
+    let encoded = base64::encode(credential::public_key(canister));
+    let lines : Vec<&str> = encoded.as_bytes()
+        .chunks(64)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect();
+    format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", lines.join("\n"))
+}
+
+// 推文与链下内容按时间线倒序取最近OUTBOX_ITEMS条, 包装为Create活动
+pub fn render_outbox(s : &State, base : &str) -> Vec<u8> {
+    let stores = s.stores.borrow();
+    let items : Vec<&Storage> = s.timeline.borrow().iter().rev()
+        .filter(|(_, content_type, _)| matches!(content_type, ContentType::Twitter | ContentType::OffChain))
+        .filter_map(|(_, content_type, uuid)| stores.get(content_type)?.get(uuid))
+        .collect();
+    let actor = format!("{}{}", base, ACTOR_PATH);
+    let ordered_items : Vec<Value> = items.iter()
+        .take(OUTBOX_ITEMS)
+        .map(|item| {
+            let object = object(s, item, base, &actor);
+            json!({
+                "id": format!("{}#create", object["id"].as_str().unwrap_or_default()),
+                "type": "Create",
+                "actor": actor,
+                "published": object["published"],
+                "to": object["to"],
+                "object": object,
+            })
+        })
+        .collect();
+    json!({
+        "@context": AS_CONTEXT,
+        "id": format!("{}{}", base, OUTBOX_PATH),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": ordered_items,
+    }).to_string().into_bytes()
+}
+
+// 推文与链下内容各自的对象文档: 路径 -> 文档, 对象的id即其地址
+pub fn render_objects(s : &State, base : &str) -> BTreeMap<String, Vec<u8>> {
+    let stores = s.stores.borrow();
+    let actor = format!("{}{}", base, ACTOR_PATH);
+    [ContentType::Twitter, ContentType::OffChain].iter()
+        .filter_map(|content_type| stores.get(content_type))
+        .flat_map(|store| store.values())
+        .map(|item| {
+            let mut object = object(s, item, base, &actor);
+            object["@context"] = json!(AS_CONTEXT);
+            (object_path(item), object.to_string().into_bytes())
+        })
+        .collect()
+}
+
+pub fn object_path(item : &Storage) -> String {
+    format!("{}{}/{}", OBJECTS_PREFIX, item.content_type.name(), item.uuid)
+}
+
+// 未实现关注, followers与following为空集合; inbox不处理投递, 也为空集合
+pub fn render_empty_collection(base : &str, path : &str) -> Vec<u8> {
+    json!({
+        "@context": AS_CONTEXT,
+        "id": format!("{}{}", base, path),
+        "type": "OrderedCollection",
+        "totalItems": 0,
+        "orderedItems": [],
+    }).to_string().into_bytes()
+}
+
+fn object(s : &State, item : &Storage, base : &str, actor : &str) -> Value {
+    let (time, _, _) = item.timeline_key();
+    let (kind, text, url, attachments) = match &item.content {
+        Contents::TwitterContent(t) => {
+            let mut attachments : Vec<&str> = t.image_urls.iter().map(|u| u.as_str()).collect();
+            attachments.push(t.video_url.as_str());
+            ("Note", t.text_content.as_str(), t.url.as_str(), attachments)
+        },
+        Contents::OffChainContent(o) if o.text_content.chars().count() > NOTE_MAX_LEN => {
+            ("Article", o.text_content.as_str(), o.url.as_str(), vec![])
+        },
+        Contents::OffChainContent(o) => ("Note", o.text_content.as_str(), o.url.as_str(), vec![]),
+        Contents::Custom(_) => ("Note", "", "", vec![]),
+    };
+    let mut object = json!({
+        "id": format!("{}{}", base, object_path(item)),
+        "type": kind,
+        "attributedTo": actor,
+        "content": text.lines()
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| format!("<p>{}</p>", escape(p)))
+            .collect::<String>(),
+        "published": timestamp::format_rfc3339(time),
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": [format!("{}{}", base, FOLLOWERS_PATH)],
+        "attachment": attachments.into_iter()
+            .filter_map(|url| attachment(s, url, base))
+            .collect::<Vec<Value>>(),
+    });
+    if kind == "Article" {
+        object["name"] = json!(truncate(text.lines().next().unwrap_or_default().trim(), MAX_TITLE_LEN));
+    };
+    if let Some(url) = safe_url(url, base) {
+        object["url"] = json!(url);
+    };
+    if let Some(edit_time) = item.edit_time {
+        object["updated"] = json!(timestamp::format_rfc3339(edit_time));
+    };
+    object
+}
+
+// 本canister的媒体附带mediaType
+fn attachment(s : &State, url : &str, base : &str) -> Option<Value> {
+    let url = safe_url(url, base)?;
+    let mut doc = json!({ "type": "Document", "url": url });
+    if let Some(key) = url.strip_prefix(base).and_then(|p| p.strip_prefix("/media/")) {
+        if let Some(asset) = s.media.borrow().get(key) {
+            doc["mediaType"] = json!(asset.content_type);
+        };
+    };
+    Some(doc)
+}
+
+// ActivityPub的content与summary为HTML, 与模板使用相同的转义
+fn escape(text : &str) -> String {
+    MarkupDisplay::new_unsafe(text, Html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{OffChainContent, TwitterContent, ID};
+
+    const BASE : &str = "https://sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io";
+
+    fn canister() -> Principal {
+        Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap()
+    }
+
+    fn parse(body : Vec<u8>) -> Value {
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn item(content_type : ContentType, uuid : &str, content : Contents) -> Storage {
+        Storage {
+            owner: String::new(),
+            uuid: uuid.to_string(),
+            content_type,
+            content,
+            d_platform: String::new(),
+            is_minted: false,
+            mint_time: None,
+            upload_time: 1_700_000_000_000_000_000,
+            edit_time: None,
+        }
+    }
+
+    fn state() -> State {
+        let s = State::default();
+        *s.name.borrow_mut() = "Alice <script>".to_string();
+        let bound = ID { platform: "github".to_string(), identity: "<b>\"alice\"</b>".to_string(), bind_time: 1 };
+        s.ids.borrow_mut().insert(bound.clone());
+        *s.main_id.borrow_mut() = bound;
+        let items = [
+            item(ContentType::Twitter, "t1", Contents::TwitterContent(TwitterContent {
+                url: "https://twitter.com/alice/status/1".to_string(),
+                text_content: "hello <img src=x onerror=alert(1)>".to_string(),
+                text_url: String::new(),
+                image_urls: vec![],
+                video_url: String::new(),
+                post_time: 1_600_000_000_000_000_000,
+            })),
+            item(ContentType::OffChain, "o1", Contents::OffChainContent(OffChainContent {
+                text_content: "note".to_string(),
+                ..Default::default()
+            })),
+        ];
+        for item in items {
+            s.timeline.borrow_mut().insert(item.timeline_key());
+            s.stores.borrow_mut().entry(item.content_type.clone()).or_default().insert(item.uuid.clone(), item);
+        }
+        s
+    }
+
+    #[test]
+    fn webfinger_document() {
+        let doc = parse(render_webfinger(BASE));
+        assert_eq!(doc["subject"], "acct:xid@sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io");
+        assert_eq!(doc["links"][0]["rel"], "self");
+        assert_eq!(doc["links"][0]["type"], "application/activity+json");
+        assert_eq!(doc["links"][0]["href"], format!("{}{}", BASE, ACTOR_PATH));
+
+        assert!(webfinger_matches(BASE, "acct:xid@sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io"));
+        assert!(webfinger_matches(BASE, "ACCT:XID@sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io"));
+        assert!(webfinger_matches(BASE, &format!("{}{}", BASE, ACTOR_PATH)));
+        assert!(webfinger_matches(BASE, &format!("{}/", BASE)));
+        assert!(!webfinger_matches(BASE, "acct:alice@sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io"));
+        assert!(!webfinger_matches(BASE, "acct:xid@aaaaa-aa.icp0.io"));
+        assert!(!webfinger_matches(BASE, ""));
+    }
+
+    #[test]
+    fn actor_document() {
+        let s = state();
+        let actor = parse(render_actor(&s, BASE, canister()));
+        let id = format!("{}{}", BASE, ACTOR_PATH);
+        assert_eq!(actor["id"], id);
+        assert_eq!(actor["type"], "Person");
+        assert_eq!(actor["name"], "Alice <script>");
+        assert_eq!(actor["inbox"], format!("{}{}", BASE, INBOX_PATH));
+        assert_eq!(actor["outbox"], format!("{}{}", BASE, OUTBOX_PATH));
+        assert!(actor.get("icon").is_none());
+
+        // 修改name不影响用户名
+        assert_eq!(actor["preferredUsername"], USERNAME);
+        *s.name.borrow_mut() = "bob".to_string();
+        assert_eq!(parse(render_actor(&s, BASE, canister()))["preferredUsername"], USERNAME);
+
+        let summary = actor["summary"].as_str().unwrap();
+        assert!(!summary.contains("<b>") && !summary.contains('"'), "{}", summary);
+        assert!(summary.contains("&lt;b&gt;"));
+        assert!(!actor["attachment"][0]["value"].as_str().unwrap().contains('<'));
+
+        let key = &actor["publicKey"];
+        assert_eq!(key["id"], format!("{}#main-key", id));
+        assert_eq!(key["owner"], id);
+        let pem = key["publicKeyPem"].as_str().unwrap();
+        let body : String = pem.lines()
+            .filter(|line| !line.starts_with("-----"))
+            .inspect(|line| assert!(line.len() <= 64))
+            .collect();
+        assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\n"));
+        assert!(pem.ends_with("-----END PUBLIC KEY-----\n"));
+        assert_eq!(base64::decode(body).unwrap(), credential::public_key(canister()));
+    }
+
+    #[test]
+    fn outbox_objects_are_served() {
+        let s = state();
+        let objects = render_objects(&s, BASE);
+        assert_eq!(objects.keys().collect::<Vec<_>>(), vec!["/objects/OffChain/o1", "/objects/Twitter/t1"]);
+        let outbox = parse(render_outbox(&s, BASE));
+        assert_eq!(outbox["totalItems"], 2);
+        for activity in outbox["orderedItems"].as_array().unwrap() {
+            let object_id = activity["object"]["id"].as_str().unwrap();
+            let path = object_id.strip_prefix(BASE).unwrap();
+            let served = parse(objects[path].clone());
+            assert_eq!(served["id"], object_id);
+            assert_eq!(served["attributedTo"], format!("{}{}", BASE, ACTOR_PATH));
+            assert_eq!(activity["id"], format!("{}#create", object_id));
+        }
+        let tweet = parse(objects["/objects/Twitter/t1"].clone());
+        assert_eq!(tweet["type"], "Note");
+        assert_eq!(tweet["content"], "<p>hello &lt;img src=x onerror=alert(1)&gt;</p>");
+        assert_eq!(tweet["url"], "https://twitter.com/alice/status/1");
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use serde_json::Value;
+use crate::http::{HttpRequest, HttpResponse, build_json, build_preflight};
+use crate::types::{ContentType, ContentUuid, ListArgs, SortOrder, StoreFilter, TimeRange, XidError};
+
+pub const API_PREFIX : &str = "/api";
+pub const DEFAULT_PAGE_SIZE : u64 = 20;
+
+// 只读JSON接口; 响应随查询参数变化, 不在证书树中, 以query返回且未经校验,
+// 需校验证书的网关会拒绝这些响应, 应经raw域名访问; 需要完整性的客户端应使用candid接口
+// 身份按调用者的可见范围过滤, 经网关访问时仅含公开身份
+// ns时间戳(*_time字段)超出JS的安全整数范围, 以字符串返回
+// GET /api/xid
+// GET /api/ids?from=&to=
+// GET /api/store?type=&cursor=&limit=&order=asc|desc&minted=&platform=&from=&to=
+// GET /api/store/<type>/<uuid>
+// GET /api/timeline?cursor=&limit=&from=&to=
+pub fn is_api_path(path : &str) -> bool {
+    path == API_PREFIX || path.starts_with("/api/")
+}
+
+pub fn handle(request : &HttpRequest, path : &str) -> HttpResponse {
+    let response = match request.method.to_uppercase().as_str() {
+        "OPTIONS" => build_preflight(),
+        "GET" | "HEAD" => route(request, path),
+        _ => ApiError::Status(405, "MethodNotAllowed").into_response(),
+    };
+    if request.method.eq_ignore_ascii_case("HEAD") { response.without_body() } else { response }
+}
+
+fn route(request : &HttpRequest, path : &str) -> HttpResponse {
+    let params = parse_query(&request.url);
+    let segments : Vec<String> = path[API_PREFIX.len()..]
+        .split('/')
+        .filter(|p| !p.is_empty())
+        .map(|p| decode(p, false))
+        .collect();
+    let segments : Vec<&str> = segments.iter().map(|p| p.as_str()).collect();
+    let result = match segments.as_slice() {
+        ["xid"] => Ok(json(&crate::get_xid())),
+        ["ids"] => time_range(&params).map(|range| json(&crate::get_ids(Some(range)))),
+        ["store"] => list_store(&params),
+        ["store", content_type, uuid] => get_item(content_type, uuid),
+        ["timeline"] => timeline(&params),
+        _ => Err(ApiError::Status(404, "NotFound")),
+    };
+    result.unwrap_or_else(|e| e.into_response())
+}
+
+fn list_store(params : &BTreeMap<String, String>) -> Result<HttpResponse, ApiError> {
+    let content_type = match params.get("type") {
+        Some(t) => ContentType::from_name(t),
+        None => return Err(ApiError::Status(400, "MissingType")),
+    };
+    let order = match params.get("order").map(|o| o.as_str()) {
+        None | Some("asc") => SortOrder::Asc,
+        Some("desc") => SortOrder::Desc,
+        Some(_) => return Err(ApiError::Status(400, "InvalidOrder")),
+    };
+    let is_minted = match params.get("minted").map(|m| m.as_str()) {
+        None => None,
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        Some(_) => return Err(ApiError::Status(400, "InvalidMinted")),
+    };
+    let range = time_range(params)?;
+    let arg = ListArgs {
+        content_type,
+        cursor: params.get("cursor").cloned(),
+        limit: param_u64(params, "limit")?.unwrap_or(DEFAULT_PAGE_SIZE),
+        filter: StoreFilter {
+            is_minted,
+            d_platform: params.get("platform").cloned(),
+            uploaded: if range.from.is_some() || range.to.is_some() { Some(range) } else { None },
+            minted: None,
+        },
+        order,
+    };
+    crate::list_store(arg).map(|page| json(&page)).map_err(ApiError::Xid)
+}
+
+fn get_item(content_type : &str, uuid : &str) -> Result<HttpResponse, ApiError> {
+    let key = ContentUuid {
+        content_type: ContentType::from_name(content_type),
+        uuid: uuid.to_string(),
+    };
+    match crate::get_store_by_uuid(vec![key]).first() {
+        Some(item) => Ok(json(item)),
+        None => Err(ApiError::Xid(XidError::UuidNotExist)),
+    }
+}
+
+fn timeline(params : &BTreeMap<String, String>) -> Result<HttpResponse, ApiError> {
+    let limit = param_u64(params, "limit")?.unwrap_or(DEFAULT_PAGE_SIZE);
+    let range = time_range(params)?;
+    crate::get_timeline(params.get("cursor").cloned(), limit, Some(range))
+        .map(|page| json(&page))
+        .map_err(ApiError::Xid)
+}
+
+fn time_range(params : &BTreeMap<String, String>) -> Result<TimeRange, ApiError> {
+    Ok(TimeRange {
+        from: param_u64(params, "from")?,
+        to: param_u64(params, "to")?,
+    })
+}
+
+fn param_u64(params : &BTreeMap<String, String>, name : &str) -> Result<Option<u64>, ApiError> {
+    match params.get(name) {
+        Some(v) => match v.parse::<u64>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(ApiError::Status(400, "InvalidParameter")),
+        },
+        None => Ok(None),
+    }
+}
+
+fn json<T : Serialize>(value : &T) -> HttpResponse {
+    let mut value = match serde_json::to_value(value) {
+        Ok(v) => v,
+        Err(_) => return ApiError::Status(500, "SerializeErr").into_response(),
+    };
+    stringify_times(&mut value);
+    build_json(200, value.to_string().into_bytes())
+}
+
+fn stringify_times(value : &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v {
+                    Value::Number(n) if key.ends_with("_time") => *v = Value::String(n.to_string()),
+                    _ => stringify_times(v),
+                };
+            }
+        },
+        Value::Array(items) => items.iter_mut().for_each(stringify_times),
+        _ => {},
+    };
+}
+
+enum ApiError {
+    Status(u16, &'static str),
+    Xid(XidError),
+}
+
+impl ApiError {
+    // 错误体: {"error": <XidError或错误名>}
+    fn into_response(self) -> HttpResponse {
+        let (status, body) = match self {
+            ApiError::Status(status, name) => (status, serde_json::json!({ "error": name })),
+            ApiError::Xid(e) => {
+                let status = match e {
+                    XidError::UuidNotExist | XidError::DataNotExist | XidError::XidNotExist => 404,
+                    _ => 400,
+                };
+                (status, serde_json::json!({ "error": e }))
+            },
+        };
+        build_json(status, body.to_string().into_bytes())
+    }
+}
+
+pub fn parse_query(url : &str) -> BTreeMap<String, String> {
+    let query = match url.split_once('?') {
+        Some((_, q)) => q,
+        None => return BTreeMap::new(),
+    };
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (decode(k, true), decode(v, true)),
+            None => (decode(pair, true), String::new()),
+        })
+        .collect()
+}
+
+// 百分号解码, 查询参数中的+视为空格
+fn decode(s : &str, plus_as_space : bool) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => match s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                Some(b) => {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                },
+                None => out.push(b'%'),
+            },
+            b'+' if plus_as_space => out.push(b' '),
+            b => out.push(b),
+        };
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_bytes::ByteBuf;
+
+    fn request(method : &str, url : &str) -> HttpRequest {
+        HttpRequest { method: method.to_string(), url: url.to_string(), headers: vec![], body: ByteBuf::new() }
+    }
+
+    fn body_json(response : &HttpResponse) -> Value {
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[test]
+    fn query_parsing() {
+        let params = parse_query("/api/store?type=Twitter&cursor=a%2Fb%3D&q=hello+world&flag&&limit=");
+        assert_eq!(params.get("type").map(String::as_str), Some("Twitter"));
+        assert_eq!(params.get("cursor").map(String::as_str), Some("a/b="));
+        assert_eq!(params.get("q").map(String::as_str), Some("hello world"));
+        assert_eq!(params.get("flag").map(String::as_str), Some(""));
+        assert_eq!(params.get("limit").map(String::as_str), Some(""));
+        assert_eq!(params.len(), 5);
+        assert!(parse_query("/api/xid").is_empty());
+        assert_eq!(decode("100%", false), "100%");
+        assert_eq!(decode("%zz+%e4%bd%a0", false), "%zz+你");
+    }
+
+    #[test]
+    fn time_range_params() {
+        let range = time_range(&parse_query("?from=1&to=2")).ok().unwrap();
+        assert_eq!((range.from, range.to), (Some(1), Some(2)));
+        let range = time_range(&parse_query("?to=2")).ok().unwrap();
+        assert_eq!((range.from, range.to), (None, Some(2)));
+        for query in ["?from=-1", "?to=x", "?from=", "?from=18446744073709551616"] {
+            assert!(matches!(time_range(&parse_query(query)), Err(ApiError::Status(400, "InvalidParameter"))), "{}", query);
+        }
+    }
+
+    #[test]
+    fn error_mapping() {
+        let response = ApiError::Xid(XidError::UuidNotExist).into_response();
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(body_json(&response), serde_json::json!({ "error": "UuidNotExist" }));
+        assert_eq!(ApiError::Xid(XidError::DataNotExist).into_response().status_code(), 404);
+        assert_eq!(ApiError::Xid(XidError::FieldOutOfRange).into_response().status_code(), 400);
+        let response = ApiError::Xid(XidError::InvalidContent("bad".to_string())).into_response();
+        assert_eq!(body_json(&response), serde_json::json!({ "error": { "InvalidContent": "bad" } }));
+        let response = ApiError::Status(400, "MissingType").into_response();
+        assert_eq!(body_json(&response), serde_json::json!({ "error": "MissingType" }));
+        let response = list_store(&parse_query("?order=asc")).err().unwrap().into_response();
+        assert_eq!(response.status_code(), 400);
+    }
+
+    #[test]
+    fn methods_and_head() {
+        let response = handle(&request("GET", "/api/unknown"), "/api/unknown");
+        assert_eq!(response.status_code(), 404);
+        assert!(!response.body().is_empty());
+        let response = handle(&request("HEAD", "/api/unknown"), "/api/unknown");
+        assert_eq!(response.status_code(), 404);
+        assert!(response.body().is_empty());
+        assert_eq!(handle(&request("POST", "/api/xid"), "/api/xid").status_code(), 405);
+        assert_eq!(handle(&request("options", "/api/xid"), "/api/xid").status_code(), 204);
+    }
+
+    #[test]
+    fn timestamps_are_strings() {
+        let value = serde_json::json!({
+            "upload_time": 1_700_000_000_000_000_000u64,
+            "mint_time": null,
+            "items": [{ "bind_time": 1, "limit": 20 }],
+        });
+        let response = json(&value);
+        assert_eq!(body_json(&response), serde_json::json!({
+            "upload_time": "1700000000000000000",
+            "mint_time": null,
+            "items": [{ "bind_time": "1", "limit": 20 }],
+        }));
+    }
+}
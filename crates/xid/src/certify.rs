@@ -0,0 +1,225 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use ic_certified_map::{fork, fork_hash, labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+const LABEL : &[u8] = b"http_assets";
+const SIG_LABEL : &[u8] = b"sig";
+pub const AVATAR_PATH : &str = "/avatar";
+// 边界节点校验时, 路径不在证书树中则按该路径的哈希校验响应体
+pub const FALLBACK_PATH : &str = "/index.html";
+// 签名在证书树中保留的时长与数量上限, 过期后需重新prepare
+pub const SIG_EXPIRATION : u64 = 10 * 60 * 1_000_000_000;
+pub const MAX_SIGS : usize = 1000;
+
+// 待取签名: seed哈希 -> 消息哈希 -> 空叶子
+type SigTree = RbTree<Hash, RbTree<Hash, Vec<u8>>>;
+
+thread_local! {
+    // 证书树无法序列化, 独立于STATE存放, post_upgrade时重建
+    static ASSET_HASHES : RefCell<RbTree<Vec<u8>, Hash>> = const { RefCell::new(RbTree::new()) };
+    // canister签名不持久化, 升级后需重新prepare
+    static SIGNATURES : RefCell<SigTree> = const { RefCell::new(RbTree::new()) };
+    static SIG_QUEUE : RefCell<VecDeque<(u64, Hash, Hash)>> = const { RefCell::new(VecDeque::new()) };
+}
+
+pub fn hash(body : &[u8]) -> Hash {
+    Sha256::digest(body).into()
+}
+
+// 认证url路径对应响应体的sha256, 仅能在update/init/post_upgrade中调用
+pub fn put(path : &str, hash : Hash) {
+    ASSET_HASHES.with(|t| {
+        t.borrow_mut().insert(path.as_bytes().to_vec(), hash);
+    });
+    set_root();
+}
+
+pub fn remove(path : &str) {
+    ASSET_HASHES.with(|t| {
+        t.borrow_mut().delete(path.as_bytes());
+    });
+    set_root();
+}
+
+pub fn clear() {
+    ASSET_HASHES.with(|t| {
+        *t.borrow_mut() = RbTree::new();
+    });
+    set_root();
+}
+
+// 证书树根为 fork(http_assets, sig)
+fn set_root() {
+    let root = fork_hash(&assets_hash(), &sigs_hash());
+    ic_cdk::api::set_certified_data(&root);
+}
+
+fn assets_hash() -> Hash {
+    ASSET_HASHES.with(|t| labeled_hash(LABEL, &t.borrow().root_hash()))
+}
+
+fn sigs_hash() -> Hash {
+    SIGNATURES.with(|t| labeled_hash(SIG_LABEL, &t.borrow().root_hash()))
+}
+
+// IC-Certificate响应头, 非query调用或路径与FALLBACK_PATH均未认证时为None
+pub fn certificate_header(path : &str) -> Option<(String, String)> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    ASSET_HASHES.with(|t| {
+        let tree = t.borrow();
+        let witness = fork(
+            labeled(LABEL, asset_witness(&tree, path)?),
+            HashTree::Pruned(sigs_hash()),
+        );
+        Some((
+            "IC-Certificate".to_string(),
+            format!("certificate=:{}:, tree=:{}:",
+                    base64::encode(certificate),
+                    base64::encode(cbor(&witness)?)),
+        ))
+    })
+}
+
+// 路径不在树中时, 见证同时包含其不存在的证明与FALLBACK_PATH的哈希
+fn asset_witness<'a>(tree : &'a RbTree<Vec<u8>, Hash>, path : &str) -> Option<HashTree<'a>> {
+    if tree.get(path.as_bytes()).is_some() {
+        return Some(tree.witness(path.as_bytes()));
+    };
+    tree.get(FALLBACK_PATH.as_bytes())?;
+    Some(merge(tree.witness(path.as_bytes()), tree.witness(FALLBACK_PATH.as_bytes())))
+}
+
+// 合并同一棵树的两个见证, 一侧被剪枝时取另一侧
+fn merge<'a>(a : HashTree<'a>, b : HashTree<'a>) -> HashTree<'a> {
+    match (a, b) {
+        (HashTree::Pruned(_), b) => b,
+        (a, HashTree::Pruned(_)) => a,
+        (HashTree::Fork(a), HashTree::Fork(b)) => {
+            let ((al, ar), (bl, br)) = (*a, *b);
+            fork(merge(al, bl), merge(ar, br))
+        },
+        (HashTree::Labeled(label, a), HashTree::Labeled(_, b)) => labeled(label, merge(*a, *b)),
+        (a, _) => a,
+    }
+}
+
+// 将消息哈希加入sig树, 同时清理过期与超量的签名; 仅能在update中调用
+pub fn add_signature(seed : &[u8], message_hash : Hash, now : u64) {
+    let seed_hash = hash(seed);
+    SIGNATURES.with(|t| {
+        let mut sigs = t.borrow_mut();
+        SIG_QUEUE.with(|q| {
+            let mut queue = q.borrow_mut();
+            while let Some((expire, s, m)) = queue.front().cloned() {
+                if expire > now && queue.len() < MAX_SIGS {
+                    break;
+                };
+                queue.pop_front();
+                remove_sig(&mut sigs, &s, &m);
+            }
+            queue.push_back((now.saturating_add(SIG_EXPIRATION), seed_hash, message_hash));
+        });
+        if sigs.get(&seed_hash[..]).is_some() {
+            sigs.modify(&seed_hash[..], |inner| inner.insert(message_hash, vec![]));
+        } else {
+            let mut inner = RbTree::new();
+            inner.insert(message_hash, vec![]);
+            sigs.insert(seed_hash, inner);
+        };
+    });
+    set_root();
+}
+
+fn remove_sig(sigs : &mut SigTree, seed_hash : &Hash, message_hash : &Hash) {
+    let mut empty = false;
+    sigs.modify(&seed_hash[..], |inner| {
+        inner.delete(&message_hash[..]);
+        empty = inner.iter().next().is_none();
+    });
+    if empty {
+        sigs.delete(&seed_hash[..]);
+    };
+}
+
+// canister签名: CBOR编码的 {certificate, tree}, 仅能在query中调用, 消息未prepare时为None
+pub fn signature(seed : &[u8], message_hash : Hash) -> Option<Vec<u8>> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    let seed_hash = hash(seed);
+    SIGNATURES.with(|t| {
+        let sigs = t.borrow();
+        sigs.get(&seed_hash[..])?.get(&message_hash[..])?;
+        let witness = fork(
+            HashTree::Pruned(assets_hash()),
+            labeled(SIG_LABEL, sigs.nested_witness(&seed_hash[..], |inner| inner.witness(&message_hash[..]))),
+        );
+        #[derive(Serialize)]
+        struct Sig<'a> {
+            #[serde(with = "serde_bytes")]
+            certificate : Vec<u8>,
+            tree : HashTree<'a>,
+        }
+        cbor(&Sig { certificate, tree: witness })
+    })
+}
+
+fn cbor<T : Serialize>(value : &T) -> Option<Vec<u8>> {
+    let mut serializer = serde_cbor::ser::Serializer::new(vec![]);
+    serializer.self_describe().ok()?;
+    value.serialize(&mut serializer).ok()?;
+    Some(serializer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 见证中路径对应的叶子
+    fn lookup<'a>(tree : &'a HashTree<'a>, path : &str) -> Option<&'a [u8]> {
+        match tree {
+            HashTree::Fork(f) => lookup(&f.0, path).or_else(|| lookup(&f.1, path)),
+            HashTree::Labeled(label, t) if *label == path.as_bytes() => match t.as_ref() {
+                HashTree::Leaf(value) => Some(value),
+                _ => None,
+            },
+            HashTree::Labeled(_, t) => lookup(t, path),
+            _ => None,
+        }
+    }
+
+    fn assets(paths : &[&str]) -> RbTree<Vec<u8>, Hash> {
+        let mut tree = RbTree::new();
+        for path in paths {
+            tree.insert(path.as_bytes().to_vec(), hash(path.as_bytes()));
+        }
+        tree
+    }
+
+    #[test]
+    fn witness_for_present_path() {
+        let tree = assets(&["/", FALLBACK_PATH, AVATAR_PATH, "/feed.xml", "/media/a", "/media/b"]);
+        for path in ["/", FALLBACK_PATH, AVATAR_PATH, "/media/b"] {
+            let witness = asset_witness(&tree, path).unwrap();
+            assert_eq!(witness.reconstruct(), tree.root_hash());
+            assert_eq!(lookup(&witness, path), Some(&hash(path.as_bytes())[..]));
+            if path != FALLBACK_PATH {
+                assert_eq!(lookup(&witness, FALLBACK_PATH), None);
+            };
+        }
+    }
+
+    #[test]
+    fn absent_path_falls_back_with_absence_proof() {
+        let tree = assets(&["/", FALLBACK_PATH, AVATAR_PATH, "/feed.xml", "/media/a"]);
+        for path in ["/avatar/1", "/avatar/x.png", "/.well-known/x", "/zzz"] {
+            let witness = asset_witness(&tree, path).unwrap();
+            assert_eq!(witness.reconstruct(), tree.root_hash());
+            assert_eq!(lookup(&witness, path), None);
+            assert_eq!(lookup(&witness, FALLBACK_PATH), Some(&hash(FALLBACK_PATH.as_bytes())[..]));
+        }
+        // 证书树中无FALLBACK_PATH时不附带证书
+        assert!(asset_witness(&assets(&[AVATAR_PATH]), "/avatar/1").is_none());
+        assert!(asset_witness(&RbTree::new(), AVATAR_PATH).is_none());
+    }
+}
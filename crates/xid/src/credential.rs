@@ -0,0 +1,211 @@
+use candid::Principal;
+use ic_certified_map::Hash;
+use serde_json::{json, Value};
+use crate::certify;
+use crate::did::did;
+use crate::timestamp;
+use crate::types::{BindingProof, CredentialFormat, IssuedCredential, PreparedCredential, ProofMethod, ID};
+
+// 凭证以canister签名签发: 公钥由canister id与SIG_SEED派生, 验证方仅需IC根公钥即可离线校验
+pub const SIG_SEED : &[u8] = b"xid-credential";
+pub const CREDENTIAL_TYPE : &str = "XidIdentityBinding";
+pub const SIGNATURE_TYPE : &str = "IcCanisterSignature";
+// 凭证有效期30天
+pub const CREDENTIAL_TTL : u64 = 30 * 24 * 3600 * 1_000_000_000;
+// 非IC身份由verify canister校验签名消息后绑定
+pub const VERIFY_CANISTER : &str = "sbcxh-pyaaa-aaaal-qbolq-cai";
+
+const VC_CONTEXT : &str = "https://www.w3.org/2018/credentials/v1";
+// 签名消息为 sha256(len(domain) || domain || signing_input)
+const SIGNING_DOMAIN : &[u8] = b"iccs_verifiable_credential";
+// canister签名公钥的DER算法标识, OID 1.3.6.1.4.1.56387.1.2
+const CANISTER_SIG_OID : [u8; 14] = [0x30, 0x0C, 0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x83, 0xB8, 0x43, 0x01, 0x02];
+
+pub fn public_key(canister : Principal) -> Vec<u8> {
+    let id = canister.as_slice();
+    let mut raw = vec![id.len() as u8];
+    raw.extend_from_slice(id);
+    raw.extend_from_slice(SIG_SEED);
+    let mut der = vec![0x30, (CANISTER_SIG_OID.len() + 3 + raw.len()) as u8];
+    der.extend_from_slice(&CANISTER_SIG_OID);
+    der.extend_from_slice(&[0x03, (raw.len() + 1) as u8, 0x00]);
+    der.extend(raw);
+    der
+}
+
+// multibase前缀u表示base64url
+pub fn public_key_multibase(canister : Principal) -> String {
+    format!("u{}", b64url(&public_key(canister)))
+}
+
+pub fn method_id(canister : Principal) -> String {
+    format!("{}#credential", did(canister))
+}
+
+pub fn message_hash(signing_input : &str) -> Hash {
+    let mut message = vec![SIGNING_DOMAIN.len() as u8];
+    message.extend_from_slice(SIGNING_DOMAIN);
+    message.extend_from_slice(signing_input.as_bytes());
+    certify::hash(&message)
+}
+
+// JWT为 header.payload; JSON-LD为不含proof的凭证, 键有序且无空白(与JCS一致)
+pub fn prepare(id : &ID, proof : Option<&BindingProof>, format : CredentialFormat, canister : Principal, now : u64)
+    -> PreparedCredential {
+    let issuer = did(canister);
+    let credential_id = format!("{}/credentials/{}", issuer, now);
+    let types = ["VerifiableCredential", CREDENTIAL_TYPE];
+    let subject = subject(id, proof, canister);
+    let signing_input = match format {
+        CredentialFormat::Jwt => {
+            // 公钥不内嵌, 由kid指向DID文档中的验证方法
+            let header = json!({
+                "alg": "IcCs",
+                "typ": "JWT",
+                "kid": method_id(canister),
+            });
+            let payload = json!({
+                "iss": issuer,
+                "sub": issuer,
+                "jti": credential_id,
+                "nbf": now / 1_000_000_000,
+                "exp": now.saturating_add(CREDENTIAL_TTL) / 1_000_000_000,
+                "vc": { "@context": [VC_CONTEXT], "type": types, "credentialSubject": subject },
+            });
+            format!("{}.{}", b64url(header.to_string().as_bytes()), b64url(payload.to_string().as_bytes()))
+        },
+        CredentialFormat::JsonLd => json!({
+            "@context": [VC_CONTEXT],
+            "id": credential_id,
+            "type": types,
+            "issuer": issuer,
+            "issuanceDate": timestamp::format_rfc3339(now),
+            "expirationDate": timestamp::format_rfc3339(now.saturating_add(CREDENTIAL_TTL)),
+            "credentialSubject": subject,
+        }).to_string(),
+    };
+    PreparedCredential { format, signing_input }
+}
+
+// 拼接签名; JSON-LD验证时去掉proof后按JCS规范化即得signing_input
+pub fn assemble(prepared : PreparedCredential, signature : &[u8], canister : Principal) -> IssuedCredential {
+    let credential = match prepared.format {
+        CredentialFormat::Jwt => format!("{}.{}", prepared.signing_input, b64url(signature)),
+        CredentialFormat::JsonLd => {
+            let mut doc : Value = serde_json::from_str(&prepared.signing_input).unwrap_or_default();
+            let created = doc["issuanceDate"].clone();
+            doc["proof"] = json!({
+                "type": SIGNATURE_TYPE,
+                "created": created,
+                "proofPurpose": "assertionMethod",
+                "verificationMethod": method_id(canister),
+                "proofValue": format!("u{}", b64url(signature)),
+            });
+            doc.to_string()
+        },
+    };
+    IssuedCredential { format: prepared.format, credential }
+}
+
+// 绑定时间与证明来源: IC身份由本canister的挑战流程验证, 其余由verify canister验证;
+// 有证明记录时附带其摘要, 可与getBindingProof的返回值比对
+fn subject(id : &ID, proof : Option<&BindingProof>, canister : Principal) -> Value {
+    let method = match proof.map(|p| &p.method) {
+        Some(ProofMethod::Secp256k1Keccak256) => "signed-message",
+        Some(ProofMethod::IcCaller) => "ic-challenge",
+        Some(ProofMethod::IcDelegation) => "ic-delegation",
+        None if id.platform == "ic" => "ic-challenge",
+        None => "signed-message",
+    };
+    let verifier = if id.platform == "ic" { did(canister) } else { format!("did:icp:{}", VERIFY_CANISTER) };
+    let mut attestation = json!({ "method": method, "verifier": verifier });
+    if let Some(proof) = proof {
+        let mut data = proof.message.to_vec();
+        data.extend_from_slice(&proof.signature);
+        attestation["proofDigest"] = json!(certify::hash(&data).iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        if let Some(key_id) = &proof.attestor_key_id {
+            attestation["attestorKeyId"] = json!(key_id);
+        };
+    };
+    json!({
+        "id": did(canister),
+        "boundIdentity": {
+            "platform": id.platform,
+            "identity": id.identity,
+            "bindTime": timestamp::format_rfc3339(id.bind_time),
+            "attestation": attestation,
+        },
+    })
+}
+
+fn b64url(data : &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SimpleId;
+    use serde_bytes::ByteBuf;
+
+    fn canister() -> Principal {
+        Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
+    }
+
+    fn bound() -> ID {
+        ID { platform: "eth".to_string(), identity: "0xabc".to_string(), bind_time: 1_700_000_000_000_000_000 }
+    }
+
+    fn decode(part : &str) -> Value {
+        serde_json::from_slice(&base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn jwt_header_references_did_method() {
+        let prepared = prepare(&bound(), None, CredentialFormat::Jwt, canister(), 1_700_000_000_000_000_000);
+        let (header, payload) = prepared.signing_input.split_once('.').unwrap();
+        let header = decode(header);
+        assert_eq!(header["kid"], json!(method_id(canister())));
+        assert_eq!(header["alg"], json!("IcCs"));
+        assert!(header.get("jwk").is_none());
+        let payload = decode(payload);
+        assert_eq!(payload["exp"].as_u64().unwrap() - payload["nbf"].as_u64().unwrap(), CREDENTIAL_TTL / 1_000_000_000);
+        let issued = assemble(prepared.clone(), b"sig", canister());
+        assert_eq!(issued.credential, format!("{}.{}", prepared.signing_input, b64url(b"sig")));
+    }
+
+    #[test]
+    fn json_ld_without_proof_is_signing_input() {
+        let proof = BindingProof {
+            id: SimpleId { platform: "eth".to_string(), identity: "0xabc".to_string() },
+            method: ProofMethod::Secp256k1Keccak256,
+            message: ByteBuf::from(b"msg".to_vec()),
+            signature: ByteBuf::from(b"sig".to_vec()),
+            attestor_key_id: Some("k1".to_string()),
+            delegation: None,
+            verified_at: 0,
+        };
+        let prepared = prepare(&bound(), Some(&proof), CredentialFormat::JsonLd, canister(), 1_700_000_000_000_000_000);
+        let issued = assemble(prepared.clone(), b"sig", canister());
+        let mut doc : Value = serde_json::from_str(&issued.credential).unwrap();
+        let attestation = &doc["credentialSubject"]["boundIdentity"]["attestation"];
+        assert_eq!(attestation["method"], json!("signed-message"));
+        assert_eq!(attestation["attestorKeyId"], json!("k1"));
+        assert_eq!(doc["proof"]["verificationMethod"], json!(method_id(canister())));
+        doc.as_object_mut().unwrap().remove("proof");
+        assert_eq!(doc.to_string(), prepared.signing_input);
+    }
+
+    #[test]
+    fn public_key_is_canister_sig_der() {
+        let key = public_key(canister());
+        let id = canister().as_slice().to_vec();
+        assert_eq!(key[0], 0x30);
+        assert_eq!(key[1] as usize, key.len() - 2);
+        assert_eq!(&key[2..16], &CANISTER_SIG_OID);
+        assert_eq!(key[18], 0x00);
+        assert_eq!(key[19] as usize, id.len());
+        assert_eq!(&key[20..20 + id.len()], &id[..]);
+        assert_eq!(&key[20 + id.len()..], SIG_SEED);
+    }
+}
@@ -0,0 +1,133 @@
+use candid::Principal;
+use serde_json::{json, Value};
+use crate::activitypub::ACTOR_PATH;
+use crate::api::API_PREFIX;
+use crate::credential;
+use crate::disclosure::public_ids;
+use crate::feed::ATOM_PATH;
+use crate::pages::public_base;
+use crate::types::{DidDocument, DidService, State, VerificationMethod, ID};
+
+pub const DID_PATH : &str = "/.well-known/did.json";
+pub const DID_TYPE : &str = "application/did+json; charset=utf-8";
+// CAIP-2 中ICP主网的链标识
+pub const ICP_CHAIN : &str = "icp:737ba355e855bd4b61279056603e0550";
+
+const DID_CONTEXT : &str = "https://www.w3.org/ns/did/v1";
+const SECP256K1_RECOVERY_CONTEXT : &str = "https://w3id.org/security/suites/secp256k1recovery-2020/v2";
+
+pub fn did(canister : Principal) -> String {
+    format!("did:icp:{}", canister)
+}
+
+// owner为唯一的认证方法, canister签名用于签发凭证;
+// 以太坊地址作为可恢复公钥的验证方法, 其余身份列为alsoKnownAs; 仅含公开身份
+pub fn document(s : &State, canister : Principal) -> DidDocument {
+    let id = did(canister);
+    let base = public_base(canister);
+    let owner = format!("{}#owner", id);
+    let mut verification_method = vec![VerificationMethod {
+        id: owner.clone(),
+        kind: "IcpPrincipal".to_string(),
+        controller: id.clone(),
+        blockchain_account_id: Some(format!("{}:{}", ICP_CHAIN, s.pub_key.borrow())),
+        public_key_multibase: None,
+    }, VerificationMethod {
+        id: credential::method_id(canister),
+        kind: credential::SIGNATURE_TYPE.to_string(),
+        controller: id.clone(),
+        blockchain_account_id: None,
+        public_key_multibase: Some(credential::public_key_multibase(canister)),
+    }];
+    let mut also_known_as = Vec::new();
+    for bound in public_ids(s).iter() {
+        if let Some(address) = eth_address(bound) {
+            verification_method.push(VerificationMethod {
+                id: format!("{}#eth-{}", id, address),
+                kind: "EcdsaSecp256k1RecoveryMethod2020".to_string(),
+                controller: id.clone(),
+                blockchain_account_id: Some(format!("eip155:1:{}", address)),
+                public_key_multibase: None,
+            });
+            also_known_as.push(format!("did:pkh:eip155:1:{}", address));
+        } else if let Some(uri) = identity_uri(bound).filter(|uri| !also_known_as.contains(uri)) {
+            also_known_as.push(uri);
+        };
+    }
+    let service = vec![
+        ("profile", "LinkedDomains", format!("{}/", base)),
+        ("activitypub", "ActivityPubActor", format!("{}{}", base, ACTOR_PATH)),
+        ("feed", "AtomFeed", format!("{}{}", base, ATOM_PATH)),
+        ("api", "XidApi", format!("{}{}", base, API_PREFIX)),
+    ].into_iter().map(|(fragment, kind, endpoint)| DidService {
+        id: format!("{}#{}", id, fragment),
+        kind: kind.to_string(),
+        service_endpoint: endpoint,
+    }).collect();
+    DidDocument {
+        controller: id.clone(),
+        id,
+        also_known_as,
+        verification_method,
+        authentication: vec![owner],
+        assertion_method: vec![credential::method_id(canister)],
+        service,
+    }
+}
+
+pub fn render(s : &State, canister : Principal) -> Vec<u8> {
+    to_json(&document(s, canister)).to_string().into_bytes()
+}
+
+// DID Core的JSON表示
+pub fn to_json(doc : &DidDocument) -> Value {
+    json!({
+        "@context": [DID_CONTEXT, SECP256K1_RECOVERY_CONTEXT],
+        "id": doc.id,
+        "controller": doc.controller,
+        "alsoKnownAs": doc.also_known_as,
+        "verificationMethod": doc.verification_method.iter().map(|m| {
+            let mut method = json!({ "id": m.id, "type": m.kind, "controller": m.controller });
+            if let Some(account) = &m.blockchain_account_id {
+                method["blockchainAccountId"] = json!(account);
+            };
+            if let Some(key) = &m.public_key_multibase {
+                method["publicKeyMultibase"] = json!(key);
+            };
+            method
+        }).collect::<Vec<Value>>(),
+        "authentication": doc.authentication,
+        "assertionMethod": doc.assertion_method,
+        "service": doc.service.iter().map(|sv| json!({
+            "id": sv.id,
+            "type": sv.kind,
+            "serviceEndpoint": sv.service_endpoint,
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+fn eth_address(id : &ID) -> Option<String> {
+    if !matches!(id.platform.to_lowercase().as_str(), "eth" | "ethereum" | "evm") {
+        return None;
+    };
+    let hex = id.identity.trim().strip_prefix("0x")?;
+    if hex.len() != 40 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    };
+    Some(format!("0x{}", hex.to_lowercase()))
+}
+
+// 无法表示为URI的身份不列出
+fn identity_uri(id : &ID) -> Option<String> {
+    let identity = id.identity.trim();
+    let handle = identity.trim_start_matches('@');
+    let is_handle = !handle.is_empty()
+        && handle.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'.'));
+    match id.platform.to_lowercase().as_str() {
+        "ic" => Principal::from_text(identity).ok().map(did),
+        "twitter" if is_handle => Some(format!("https://twitter.com/{}", handle)),
+        "github" if is_handle => Some(format!("https://github.com/{}", handle)),
+        _ if identity.starts_with("https://") || identity.starts_with("did:") => Some(identity.to_string()),
+        _ => None,
+    }
+}
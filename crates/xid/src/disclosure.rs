@@ -0,0 +1,128 @@
+use candid::Principal;
+use crate::certify;
+use crate::types::{IdVisibility, Presentation, SimpleId, State, ID};
+
+pub const MAX_SHARED : usize = 20;
+pub const MAX_PRESENTATIONS : usize = 100;
+pub const MAX_PRESENTATION_IDS : usize = 20;
+pub const MAX_PRESENTATION_TTL : u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+pub fn simple_id(id : &ID) -> SimpleId {
+    SimpleId {
+        platform: id.platform.clone(),
+        identity: id.identity.clone(),
+    }
+}
+
+// viewer为None时仅公开身份可见
+pub fn is_visible(s : &State, id : &SimpleId, viewer : Option<&Principal>) -> bool {
+    match s.visibility.borrow().get(id) {
+        None | Some(IdVisibility::Public) => true,
+        Some(IdVisibility::Private) => false,
+        Some(IdVisibility::Shared(principals)) => viewer.is_some_and(|v| principals.contains(v)),
+    }
+}
+
+// 公开页面, DID文档与ActivityPub只展示公开身份
+pub fn public_ids(s : &State) -> Vec<ID> {
+    s.ids.borrow().iter().filter(|id| is_visible(s, &simple_id(id), None)).cloned().collect()
+}
+
+pub fn public_main_id(s : &State) -> Option<ID> {
+    let main_id = s.main_id.borrow();
+    if main_id.identity.is_empty() || !is_visible(s, &simple_id(&main_id), None) {
+        return None;
+    };
+    Some(main_id.clone())
+}
+
+// token仅用于索引, 读取时另校验调用者为指定的验证方
+pub fn token(verifier : &Principal, ids : &[SimpleId], now : u64) -> String {
+    let mut data = verifier.as_slice().to_vec();
+    data.extend_from_slice(&now.to_be_bytes());
+    for id in ids {
+        data.extend_from_slice(id.platform.as_bytes());
+        data.push(0);
+        data.extend_from_slice(id.identity.as_bytes());
+        data.push(0);
+    }
+    certify::hash(&data)[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn is_expired(presentation : &Presentation, now : u64) -> bool {
+    presentation.expire_time <= now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(platform : &str, identity : &str) -> ID {
+        ID { platform: platform.to_string(), identity: identity.to_string(), bind_time: 1 }
+    }
+
+    fn state() -> State {
+        let s = State::default();
+        s.ids.borrow_mut().insert(id("twitter", "alice"));
+        s.ids.borrow_mut().insert(id("github", "alice"));
+        s.ids.borrow_mut().insert(id("email", "alice@example.com"));
+        *s.main_id.borrow_mut() = id("github", "alice");
+        s
+    }
+
+    #[test]
+    fn visibility_depends_on_viewer() {
+        let s = state();
+        let friend = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        let twitter = simple_id(&id("twitter", "alice"));
+        let email = simple_id(&id("email", "alice@example.com"));
+        s.visibility.borrow_mut().insert(email.clone(), IdVisibility::Private);
+        s.visibility.borrow_mut().insert(twitter.clone(), IdVisibility::Shared(vec![friend]));
+
+        assert!(is_visible(&s, &simple_id(&id("github", "alice")), None));
+        assert!(!is_visible(&s, &email, Some(&friend)));
+        assert!(is_visible(&s, &twitter, Some(&friend)));
+        assert!(!is_visible(&s, &twitter, Some(&other)));
+        assert!(!is_visible(&s, &twitter, None));
+    }
+
+    #[test]
+    fn public_views_skip_hidden_ids() {
+        let s = state();
+        s.visibility.borrow_mut().insert(simple_id(&id("email", "alice@example.com")), IdVisibility::Private);
+        s.visibility.borrow_mut().insert(
+            simple_id(&id("twitter", "alice")),
+            IdVisibility::Shared(vec![Principal::anonymous()]),
+        );
+        let ids : Vec<String> = public_ids(&s).into_iter().map(|id| id.platform).collect();
+        assert_eq!(ids, vec!["github"]);
+        assert_eq!(public_main_id(&s).map(|id| id.platform), Some("github".to_string()));
+
+        s.visibility.borrow_mut().insert(simple_id(&id("github", "alice")), IdVisibility::Private);
+        assert!(public_ids(&s).is_empty());
+        assert!(public_main_id(&s).is_none());
+        *s.main_id.borrow_mut() = ID::default();
+        s.visibility.borrow_mut().clear();
+        assert!(public_main_id(&s).is_none());
+    }
+
+    #[test]
+    fn presentation_tokens_and_expiry() {
+        let verifier = Principal::anonymous();
+        let ids = [simple_id(&id("github", "alice"))];
+        assert_eq!(token(&verifier, &ids, 1).len(), 32);
+        assert_ne!(token(&verifier, &ids, 1), token(&verifier, &ids, 2));
+        assert_ne!(token(&verifier, &ids, 1), token(&verifier, &[], 1));
+
+        let presentation = Presentation {
+            token: token(&verifier, &ids, 1),
+            verifier,
+            ids: ids.to_vec(),
+            create_time: 1,
+            expire_time: 10,
+        };
+        assert!(!is_expired(&presentation, 9));
+        assert!(is_expired(&presentation, 10));
+    }
+}
@@ -0,0 +1,365 @@
+use askama::Template;
+use crate::activitypub::object_path;
+use crate::media;
+use crate::pages::{safe_url, truncate};
+use crate::timestamp;
+use crate::types::{ContentType, Contents, State, Storage};
+
+pub const RSS_PATH : &str = "/feed.rss";
+pub const ATOM_PATH : &str = "/feed.atom";
+pub const RSS_TYPE : &str = "application/rss+xml; charset=utf-8";
+pub const ATOM_TYPE : &str = "application/atom+xml; charset=utf-8";
+pub const FEED_ITEMS : usize = 50;
+pub const MAX_TITLE_LEN : usize = 80;
+
+struct Enclosure {
+    url : String,
+    mime : String,
+    length : Option<u64>, // 外部链接的长度未知
+}
+
+struct FeedEntry {
+    id : String,
+    title : String,
+    link : Option<String>,
+    content : String,
+    pub_date : String,
+    published : String,
+    updated : String,
+    enclosures : Vec<Enclosure>,
+}
+
+impl FeedEntry {
+    // RSS每个item只允许一个enclosure且length必填, 取第一个长度已知的附件
+    fn rss_enclosure(&self) -> Option<(&Enclosure, u64)> {
+        self.enclosures.iter().find_map(|e| Some((e, e.length?)))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "rss.xml")]
+struct RssFeed<'a> {
+    title : &'a str,
+    description : &'a str,
+    link : &'a str,
+    self_url : String,
+    updated : String,
+    entries : &'a [FeedEntry],
+}
+
+#[derive(Template)]
+#[template(path = "atom.xml")]
+struct AtomFeed<'a> {
+    title : &'a str,
+    description : &'a str,
+    link : &'a str,
+    self_url : String,
+    updated : String,
+    author : &'a str,
+    entries : &'a [FeedEntry],
+}
+
+struct Channel {
+    name : String,
+    title : String,
+    description : String,
+    link : String,
+    updated : u64,
+    entries : Vec<FeedEntry>,
+}
+
+pub fn render_rss(s : &State, base : &str) -> Vec<u8> {
+    let channel = channel(s, base);
+    let feed = RssFeed {
+        title: &channel.title,
+        description: &channel.description,
+        link: &channel.link,
+        self_url: format!("{}{}", base, RSS_PATH),
+        updated: timestamp::format_http_date(channel.updated),
+        entries: &channel.entries,
+    };
+    feed.render().unwrap_or_default().into_bytes()
+}
+
+pub fn render_atom(s : &State, base : &str) -> Vec<u8> {
+    let channel = channel(s, base);
+    let feed = AtomFeed {
+        title: &channel.title,
+        description: &channel.description,
+        link: &channel.link,
+        self_url: format!("{}{}", base, ATOM_PATH),
+        updated: timestamp::format_rfc3339(channel.updated),
+        author: &channel.name,
+        entries: &channel.entries,
+    };
+    feed.render().unwrap_or_default().into_bytes()
+}
+
+// 推文与链下内容按时间线倒序取最近FEED_ITEMS条
+fn channel(s : &State, base : &str) -> Channel {
+    let name = s.name.borrow();
+    let name = if name.is_empty() { "xid".to_string() } else { xml_text(&name) };
+    let stores = s.stores.borrow();
+    let mut updated = 0;
+    let entries = s.timeline.borrow().iter().rev()
+        .filter(|(_, content_type, _)| matches!(content_type, ContentType::Twitter | ContentType::OffChain))
+        .filter_map(|(_, content_type, uuid)| stores.get(content_type)?.get(uuid))
+        .take(FEED_ITEMS)
+        .map(|item| {
+            let entry = entry(s, item, base);
+            updated = updated.max(item.edit_time.unwrap_or(item.upload_time));
+            entry
+        })
+        .collect();
+    Channel {
+        title: format!("{} · xid", name),
+        description: format!("Content saved by {} on xid", name),
+        link: format!("{}/", base),
+        name,
+        updated,
+        entries,
+    }
+}
+
+fn entry(s : &State, item : &Storage, base : &str) -> FeedEntry {
+    let (time, _, _) = item.timeline_key();
+    let (text, url, attachments) = match &item.content {
+        Contents::TwitterContent(t) => {
+            let mut attachments : Vec<&str> = t.image_urls.iter().map(|u| u.as_str()).collect();
+            attachments.push(t.video_url.as_str());
+            (t.text_content.as_str(), t.url.as_str(), attachments)
+        },
+        Contents::OffChainContent(o) => (o.text_content.as_str(), o.url.as_str(), vec![]),
+        Contents::Custom(_) => ("", "", vec![]),
+    };
+    let text = xml_text(text);
+    let link = safe_url(url, base).map(|u| xml_text(&u));
+    let title = match text.lines().find(|l| !l.trim().is_empty()) {
+        Some(line) => truncate(line.trim(), MAX_TITLE_LEN),
+        None => link.clone().unwrap_or_else(|| item.uuid.clone()),
+    };
+    // id为ActivityPub对象地址, 同时作为RSS的guid, 含内容类型以免不同类型的uuid冲突
+    FeedEntry {
+        id: format!("{}{}", base, xml_text(&object_path(item))),
+        title,
+        link,
+        content: text,
+        pub_date: timestamp::format_http_date(time),
+        published: timestamp::format_rfc3339(time),
+        updated: timestamp::format_rfc3339(item.edit_time.unwrap_or(item.upload_time).max(time)),
+        enclosures: attachments.into_iter()
+            .filter_map(|url| enclosure(s, url, base))
+            .collect(),
+    }
+}
+
+// 本canister的媒体可取得准确的类型与长度, 外部链接按扩展名推断类型, 长度未知
+fn enclosure(s : &State, url : &str, base : &str) -> Option<Enclosure> {
+    let url = xml_text(&safe_url(url, base)?);
+    if let Some(key) = url.strip_prefix(base).and_then(|p| p.strip_prefix("/media/")) {
+        if let Some(asset) = s.media.borrow().get(key) {
+            return Some(Enclosure {
+                url: format!("{}{}", base, media::media_path(&asset.key)),
+                mime: asset.content_type.clone(),
+                length: Some(asset.length),
+            });
+        };
+    };
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let ext = path.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+    let mime = match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    };
+    Some(Enclosure { url, mime: mime.to_string(), length: None })
+}
+
+// XML 1.0不允许除制表符与换行外的控制字符
+fn xml_text(text : &str) -> String {
+    text.chars().filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r')).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_bytes::ByteBuf;
+    use crate::types::{MediaAsset, TwitterContent};
+
+    const BASE : &str = "https://sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io";
+
+    #[derive(Debug)]
+    struct Element {
+        path : String,
+        attrs : Vec<(String, String)>,
+        text : String,
+    }
+
+    impl Element {
+        fn attr(&self, name : &str) -> Option<&str> {
+            self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+        }
+    }
+
+    fn unescape(text : &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('&') {
+            out.push_str(&rest[..start]);
+            let end = rest[start..].find(';').expect("unterminated entity") + start;
+            let entity = &rest[start + 1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = match entity.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16),
+                        None => entity.strip_prefix('#').expect("unknown entity").parse(),
+                    };
+                    char::from_u32(code.expect("bad character reference")).unwrap()
+                },
+            };
+            out.push(c);
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    // 最小的XML解析: 检查标签配对, 属性引号与实体, 按文档顺序返回元素
+    fn parse_xml(xml : &str) -> Vec<Element> {
+        let xml = xml.strip_prefix("<?xml version=\"1.0\" encoding=\"utf-8\"?>").expect("missing declaration");
+        let mut elements : Vec<Element> = Vec::new();
+        let mut stack : Vec<(String, usize)> = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            assert!(!text.contains('>'), "stray > in {:?}", text);
+            match stack.last() {
+                Some((_, index)) => elements[*index].text.push_str(&unescape(text)),
+                None => assert!(text.trim().is_empty(), "text outside root: {:?}", text),
+            };
+            let end = rest[start..].find('>').expect("unterminated tag") + start;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                let (open, _) = stack.pop().expect("unbalanced close tag");
+                assert_eq!(open, name);
+                continue;
+            };
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/').trim();
+            let (name, mut attributes) = tag.split_once(' ').unwrap_or((tag, ""));
+            let mut attrs = Vec::new();
+            while !attributes.trim().is_empty() {
+                let (key, value) = attributes.trim_start().split_once("=\"").expect("unquoted attribute");
+                let (value, remaining) = value.split_once('"').expect("unterminated attribute");
+                assert!(!value.contains('<'));
+                attrs.push((key.to_string(), unescape(value)));
+                attributes = remaining;
+            }
+            let path = stack.iter().map(|(n, _)| n.as_str()).chain([name]).collect::<Vec<&str>>().join("/");
+            elements.push(Element { path, attrs, text: String::new() });
+            if !self_closing {
+                stack.push((name.to_string(), elements.len() - 1));
+            };
+        }
+        assert!(rest.trim().is_empty());
+        assert!(stack.is_empty(), "unclosed {:?}", stack);
+        elements
+    }
+
+    fn state() -> State {
+        let s = State::default();
+        *s.name.borrow_mut() = "Alice & <Bob>".to_string();
+        s.media.borrow_mut().insert("clip".to_string(), MediaAsset {
+            key: "clip".to_string(),
+            content_type: "video/mp4".to_string(),
+            chunks: vec![],
+            length: 1024,
+            sha256: ByteBuf::new(),
+            upload_time: 0,
+        });
+        let tweet = |uuid : &str, image_urls : Vec<&str>, video_url : &str| Storage {
+            owner: String::new(),
+            uuid: uuid.to_string(),
+            content_type: ContentType::Twitter,
+            content: Contents::TwitterContent(TwitterContent {
+                url: format!("https://twitter.com/alice/status/{}", uuid),
+                text_content: "line \"one\" <b>&</b>\u{1}\nline two".to_string(),
+                text_url: String::new(),
+                image_urls: image_urls.into_iter().map(String::from).collect(),
+                video_url: video_url.to_string(),
+                post_time: 1_600_000_000_000_000_000,
+            }),
+            d_platform: String::new(),
+            is_minted: false,
+            mint_time: None,
+            upload_time: 1_700_000_000_000_000_000,
+            edit_time: None,
+        };
+        let items = [
+            tweet("1", vec!["https://pbs.twimg.com/a.jpg", "https://pbs.twimg.com/b.png?x=1&y=2"], "/media/clip"),
+            tweet("2", vec!["https://pbs.twimg.com/c.jpg"], ""),
+        ];
+        for item in items {
+            s.timeline.borrow_mut().insert(item.timeline_key());
+            s.stores.borrow_mut().entry(item.content_type.clone()).or_default().insert(item.uuid.clone(), item);
+        }
+        s
+    }
+
+    #[test]
+    fn rss_is_well_formed() {
+        let s = state();
+        let rss = String::from_utf8(render_rss(&s, BASE)).unwrap();
+        let elements = parse_xml(&rss);
+        let find = |path : &str| elements.iter().filter(|e| e.path == path).collect::<Vec<&Element>>();
+        assert_eq!(find("rss/channel/title")[0].text, "Alice & <Bob> · xid");
+        assert_eq!(find("rss/channel/item").len(), 2);
+
+        let guids : Vec<&str> = find("rss/channel/item/guid").iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(guids, vec![format!("{}/objects/Twitter/2", BASE), format!("{}/objects/Twitter/1", BASE)]);
+        assert!(find("rss/channel/item/guid").iter().all(|e| e.attr("isPermaLink") == Some("false")));
+        assert!(find("rss/channel/item/description")[0].text.starts_with("line \"one\" <b>&</b>\nline two"));
+
+        // 每个item至多一个enclosure, 只取长度已知的本地媒体
+        let enclosures = find("rss/channel/item/enclosure");
+        assert_eq!(enclosures.len(), 1);
+        assert_eq!(enclosures[0].attr("url"), Some(format!("{}/media/clip", BASE).as_str()));
+        assert_eq!(enclosures[0].attr("length"), Some("1024"));
+        assert_eq!(enclosures[0].attr("type"), Some("video/mp4"));
+    }
+
+    #[test]
+    fn atom_is_well_formed() {
+        let s = state();
+        let atom = String::from_utf8(render_atom(&s, BASE)).unwrap();
+        let elements = parse_xml(&atom);
+        let find = |path : &str| elements.iter().filter(|e| e.path == path).collect::<Vec<&Element>>();
+        assert_eq!(find("feed/author/name")[0].text, "Alice & <Bob>");
+        let ids : Vec<&str> = find("feed/entry/id").iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(ids, vec![format!("{}/objects/Twitter/2", BASE), format!("{}/objects/Twitter/1", BASE)]);
+        assert_eq!(find("feed/entry/published")[0].text, timestamp::format_rfc3339(1_600_000_000_000_000_000));
+
+        let enclosures : Vec<&Element> = find("feed/entry/link").into_iter()
+            .filter(|e| e.attr("rel") == Some("enclosure"))
+            .collect();
+        assert_eq!(enclosures.len(), 4);
+        let b = enclosures.iter().find(|e| e.attr("href") == Some("https://pbs.twimg.com/b.png?x=1&y=2")).unwrap();
+        assert_eq!(b.attr("type"), Some("image/png"));
+        assert_eq!(b.attr("length"), None);
+        let clip = enclosures.iter().find(|e| e.attr("type") == Some("video/mp4")).unwrap();
+        assert_eq!(clip.attr("length"), Some("1024"));
+    }
+}
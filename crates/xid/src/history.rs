@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::types::{Contents, FieldChange};
+
+pub const MAX_VERSIONS : usize = 20;
+
+// 将内容展开为 字段名 -> 值, 用于逐字段比较
+fn fields(content : &Contents, d_platform : &str) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::new();
+    map.insert("d_platform".to_string(), d_platform.to_string());
+    match content {
+        Contents::TwitterContent(t) => {
+            map.insert("url".to_string(), t.url.clone());
+            map.insert("text_content".to_string(), t.text_content.clone());
+            map.insert("text_url".to_string(), t.text_url.clone());
+            map.insert("image_urls".to_string(), t.image_urls.join("\n"));
+            map.insert("video_url".to_string(), t.video_url.clone());
+            map.insert("post_time".to_string(), t.post_time.to_string());
+        },
+        Contents::OffChainContent(o) => {
+            map.insert("local_content_type".to_string(), o.local_content_type.clone());
+            map.insert("file_type".to_string(), o.file_type.clone());
+            map.insert("text_content".to_string(), o.text_content.clone());
+            map.insert("url".to_string(), o.url.clone());
+        },
+        Contents::Custom(c) => {
+            map.extend(c.fields.iter().cloned());
+        },
+    };
+    map
+}
+
+// 两个版本间发生变化的字段, 新增或删除的字段一侧为None
+pub fn diff(old : &Contents, old_platform : &str, new : &Contents, new_platform : &str) -> Vec<FieldChange> {
+    let old = fields(old, old_platform);
+    let new = fields(new, new_platform);
+    let names : BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    names.into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| FieldChange {
+            field: name.clone(),
+            old: old.get(name).cloned(),
+            new: new.get(name).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{CustomContent, OffChainContent, TwitterContent};
+
+    fn changes(diff : Vec<FieldChange>) -> Vec<(String, Option<String>, Option<String>)> {
+        diff.into_iter().map(|c| (c.field, c.old, c.new)).collect()
+    }
+
+    fn change(field : &str, old : Option<&str>, new : Option<&str>) -> (String, Option<String>, Option<String>) {
+        (field.to_string(), old.map(str::to_string), new.map(str::to_string))
+    }
+
+    fn custom(fields : &[(&str, &str)]) -> Contents {
+        Contents::Custom(CustomContent {
+            content_type: "Note".to_string(),
+            fields: fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        })
+    }
+
+    #[test]
+    fn unchanged_content_has_no_diff() {
+        let c = custom(&[("text", "a")]);
+        assert!(diff(&c, "ic", &c, "ic").is_empty());
+    }
+
+    #[test]
+    fn changed_added_and_removed_fields() {
+        let old = custom(&[("text", "a"), ("tag", "x")]);
+        let new = custom(&[("text", "b"), ("link", "https://a.b")]);
+        assert_eq!(changes(diff(&old, "ic", &new, "eth")), vec![
+            change("d_platform", Some("ic"), Some("eth")),
+            change("link", None, Some("https://a.b")),
+            change("tag", Some("x"), None),
+            change("text", Some("a"), Some("b")),
+        ]);
+    }
+
+    #[test]
+    fn builtin_content_fields() {
+        let old = Contents::TwitterContent(TwitterContent {
+            image_urls: vec!["a".to_string(), "b".to_string()],
+            post_time: 1,
+            ..Default::default()
+        });
+        let new = Contents::TwitterContent(TwitterContent {
+            image_urls: vec!["a".to_string()],
+            post_time: 2,
+            ..Default::default()
+        });
+        assert_eq!(changes(diff(&old, "", &new, "")), vec![
+            change("image_urls", Some("a\nb"), Some("a")),
+            change("post_time", Some("1"), Some("2")),
+        ]);
+
+        let off_chain = Contents::OffChainContent(OffChainContent {
+            url: "https://a.b".to_string(),
+            ..Default::default()
+        });
+        let diff = changes(diff(&old, "", &off_chain, ""));
+        assert!(diff.contains(&change("url", Some(""), Some("https://a.b"))));
+        assert!(diff.contains(&change("video_url", Some(""), None)));
+        assert!(diff.contains(&change("file_type", None, Some(""))));
+    }
+}
@@ -1,6 +1,9 @@
 use crate::rc_bytes::RcBytes;
 use serde_bytes::ByteBuf;
-use ic_cdk::export::candid::{Func, Nat, CandidType, Deserialize};
+use ic_cdk::export::candid::{Func, Nat, CandidType, Deserialize, Principal};
+use crate::types::MediaAsset;
+use crate::pages::Document;
+use crate::timestamp;
 
 // HTTP interface
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -42,16 +45,131 @@ pub struct StreamingCallbackHttpResponse {
     token: Option<StreamingCallbackToken>,
 }
 
+impl HttpResponse {
+    pub fn with_header(mut self, header : (String, String)) -> Self {
+        self.headers.push(header);
+        self
+    }
+
+    // HEAD请求保留状态码与响应头, 不返回响应体
+    pub fn without_body(mut self) -> Self {
+        self.body = RcBytes::from(ByteBuf::new());
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    #[cfg(test)]
+    pub(crate) fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
 pub fn build_202(image_data : Vec<u8>,  image_type: String) -> HttpResponse {
     HttpResponse{
         status_code : 200,
-        headers : vec![(String::from("Content-Type"), String::from(image_type + ";charset=utf-8")),
+        headers : vec![(String::from("Content-Type"), image_type + ";charset=utf-8"),
                        (String::from("Cache-Control"), String::from("max-age=680400"))],
         streaming_strategy : None,
         body : RcBytes::from( ByteBuf::from(image_data)),
     }
 }
 
+// JSON接口允许跨域读取
+fn cors_headers() -> Vec<(String, String)> {
+    vec![(String::from("Access-Control-Allow-Origin"), String::from("*")),
+         (String::from("Access-Control-Allow-Methods"), String::from("GET, HEAD, OPTIONS")),
+         (String::from("Access-Control-Allow-Headers"), String::from("Content-Type"))]
+}
+
+pub fn build_json(status_code : u16, body : Vec<u8>) -> HttpResponse {
+    let mut headers = vec![(String::from("Content-Type"), String::from("application/json; charset=utf-8")),
+                           (String::from("Cache-Control"), String::from("no-cache"))];
+    headers.extend(cors_headers());
+    HttpResponse {
+        status_code,
+        headers,
+        body : RcBytes::from(ByteBuf::from(body)),
+        streaming_strategy : None,
+    }
+}
+
+pub fn build_preflight() -> HttpResponse {
+    HttpResponse {
+        status_code : 204,
+        headers : cors_headers(),
+        body : RcBytes::from(ByteBuf::new()),
+        streaming_strategy : None,
+    }
+}
+
+// 预渲染的文档, 内容随数据变化, 由证书保证一致性
+pub(crate) fn build_document(doc : &Document) -> HttpResponse {
+    HttpResponse {
+        status_code : 200,
+        headers : document_headers(doc),
+        streaming_strategy : None,
+        body : doc.body.clone(),
+    }
+}
+
+// 认证域名上总是返回完整的200, 304无响应体, 与证书中的哈希不符, 仅在raw域名返回
+pub(crate) fn serve_document(doc : &Document, request : &HttpRequest) -> HttpResponse {
+    if is_raw_domain(request) && doc.not_modified(&request.headers) {
+        build_304(doc)
+    } else {
+        build_document(doc)
+    }
+}
+
+// 重定向的响应体为回退文档, 路径不在证书树中时按certify::FALLBACK_PATH的哈希认证
+pub(crate) fn build_redirect(location : &str, fallback : &Document) -> HttpResponse {
+    HttpResponse {
+        status_code : 308,
+        headers : vec![(String::from("Location"), location.to_string()),
+                       (String::from("Content-Type"), fallback.content_type.clone()),
+                       (String::from("Cache-Control"), String::from("max-age=60"))],
+        streaming_strategy : None,
+        body : fallback.body.clone(),
+    }
+}
+
+// raw域名的响应不经边界节点校验
+pub fn is_raw_domain(request : &HttpRequest) -> bool {
+    request.headers.iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("Host"))
+        .any(|(_, v)| v.split(':').next().unwrap_or_default().contains(".raw."))
+}
+
+// 仅200响应与重定向为证书所认证的内容(媒体的响应体为流式传输的首块), 其余响应不附带证书
+pub fn is_certifiable(response : &HttpResponse) -> bool {
+    matches!(response.status_code, 200 | 308)
+}
+
+pub(crate) fn build_304(doc : &Document) -> HttpResponse {
+    HttpResponse {
+        status_code : 304,
+        headers : document_headers(doc),
+        streaming_strategy : None,
+        body : RcBytes::from(ByteBuf::new()),
+    }
+}
+
+// JSON文档(WebFinger, ActivityPub)允许跨域读取
+fn document_headers(doc : &Document) -> Vec<(String, String)> {
+    let mut headers = vec![(String::from("Content-Type"), doc.content_type.clone()),
+         (String::from("Cache-Control"), String::from("max-age=60")),
+         (String::from("ETag"), doc.etag.clone()),
+         (String::from("Last-Modified"), timestamp::format_http_date(doc.last_modified))];
+    if doc.content_type.contains("json") {
+        headers.extend(cors_headers());
+    };
+    headers
+}
+
 pub fn build_404() -> HttpResponse {
     HttpResponse {
         status_code : 404,
@@ -68,3 +186,125 @@ pub fn build_404() -> HttpResponse {
         streaming_strategy : None,
     }
 }
+
+// 首块随响应返回, 其余块由http_request_streaming_callback逐块获取
+pub fn build_media(asset : &MediaAsset, canister : Principal) -> HttpResponse {
+    let streaming_strategy = if asset.chunks.len() > 1 {
+        Some(StreamingStrategy::Callback {
+            callback: Func { principal: canister, method: "http_request_streaming_callback".to_string() },
+            token: chunk_token(asset, 1),
+        })
+    } else {
+        None
+    };
+    HttpResponse {
+        status_code : 200,
+        headers : vec![(String::from("Content-Type"), asset.content_type.clone()),
+                       (String::from("Content-Length"), asset.length.to_string()),
+                       (String::from("Cache-Control"), String::from("max-age=680400"))],
+        body : RcBytes::from(asset.chunks.first().cloned().unwrap_or_default()),
+        streaming_strategy,
+    }
+}
+
+pub fn build_chunk(asset : &MediaAsset, index : usize) -> StreamingCallbackHttpResponse {
+    StreamingCallbackHttpResponse {
+        body : RcBytes::from(asset.chunks[index].clone()),
+        token : if index + 1 < asset.chunks.len() { Some(chunk_token(asset, index + 1)) } else { None },
+    }
+}
+
+// 媒体不存在或已被替换时结束流
+pub fn build_chunk_end() -> StreamingCallbackHttpResponse {
+    StreamingCallbackHttpResponse {
+        body : RcBytes::from(ByteBuf::new()),
+        token : None,
+    }
+}
+
+fn chunk_token(asset : &MediaAsset, index : usize) -> StreamingCallbackToken {
+    StreamingCallbackToken {
+        key : asset.key.clone(),
+        content_encoding : String::from("identity"),
+        index : Nat::from(index as u64),
+        sha256 : Some(asset.sha256.clone()),
+    }
+}
+
+// 超出u64的索引返回None
+pub fn token_index(token : &StreamingCallbackToken) -> Option<usize> {
+    match token.index.0.to_u64_digits().as_slice() {
+        [] => Some(0),
+        [d] => usize::try_from(*d).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certify;
+    use crate::pages::HTML_TYPE;
+
+    const NOW : u64 = 1_700_000_000_000_000_000;
+
+    fn request(host : &str, conditional : &[(&str, String)]) -> HttpRequest {
+        let mut headers = vec![("Host".to_string(), host.to_string())];
+        headers.extend(conditional.iter().map(|(k, v)| (k.to_string(), v.clone())));
+        HttpRequest { method: "GET".to_string(), url: "/".to_string(), headers, body: ByteBuf::new() }
+    }
+
+    // 证书认证的哈希即pages::set中对响应体的sha256
+    #[test]
+    fn certified_path_only_serves_certified_body() {
+        let doc = Document::new(HTML_TYPE, b"<html>profile</html>".to_vec(), None, NOW);
+        let certified = certify::hash(b"<html>profile</html>");
+        let conditionals = [
+            vec![],
+            vec![("If-None-Match", doc.etag.clone())],
+            vec![("If-None-Match", "*".to_string())],
+            vec![("If-Modified-Since", timestamp::format_http_date(NOW))],
+            vec![("If-None-Match", "\"other\"".to_string())],
+        ];
+        for conditional in conditionals.iter() {
+            for host in ["aaaaa-aa.icp0.io", "aaaaa-aa.raw.icp0.io", "aaaaa-aa.raw.icp0.io:443"] {
+                let response = serve_document(&doc, &request(host, conditional));
+                if is_certifiable(&response) {
+                    assert_eq!(certify::hash(&response.body), certified);
+                };
+                if !host.contains(".raw.") {
+                    assert!(is_certifiable(&response), "{} {:?}", host, conditional);
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn raw_domain_honours_conditional_requests() {
+        let doc = Document::new(HTML_TYPE, b"body".to_vec(), None, NOW);
+        let raw = "aaaaa-aa.raw.icp0.io";
+        let response = serve_document(&doc, &request(raw, &[("If-None-Match", doc.etag.clone())]));
+        assert_eq!(response.status_code, 304);
+        assert!(response.body.is_empty());
+        assert!(!is_certifiable(&response));
+        let response = serve_document(&doc, &request(raw, &[("If-None-Match", "\"other\"".to_string())]));
+        assert_eq!(response.status_code, 200);
+    }
+
+    #[test]
+    fn redirect_body_is_the_fallback_document() {
+        let doc = Document::new(HTML_TYPE, b"<html>profile</html>".to_vec(), None, NOW);
+        let response = build_redirect(certify::AVATAR_PATH, &doc);
+        assert_eq!(response.status_code, 308);
+        assert!(response.headers.contains(&("Location".to_string(), certify::AVATAR_PATH.to_string())));
+        assert!(is_certifiable(&response));
+        assert_eq!(certify::hash(&response.body), certify::hash(b"<html>profile</html>"));
+    }
+
+    #[test]
+    fn unchanged_body_keeps_last_modified() {
+        let doc = Document::new(HTML_TYPE, b"body".to_vec(), None, NOW);
+        assert_eq!(Document::new(HTML_TYPE, b"body".to_vec(), Some(&doc), NOW + 1).last_modified, NOW);
+        assert_eq!(Document::new(HTML_TYPE, b"new".to_vec(), Some(&doc), NOW + 1).last_modified, NOW + 1);
+    }
+}
@@ -0,0 +1,205 @@
+// 旧版稳定存储格式: 时间字段为字符串, 仅用于升级迁移
+use std::collections::{BTreeMap, BTreeSet};
+use candid::CandidType;
+use serde::Deserialize;
+use crate::timestamp;
+use crate::types::{ID, Avatar, IcChallenge, Delegate, OwnerTransfer, RecoveryConfig,
+                   RecoveryRequest, ContentType, ContentSchema, Contents, TwitterContent,
+                   OffChainContent, CustomContent, Storage, StableState};
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyID {
+    pub platform : String,
+    pub identity : String,
+    pub bind_time : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyTwitterContent {
+    pub url : String,
+    pub text_content : String,
+    pub text_url : String,
+    pub image_urls : Vec<String>,
+    pub video_url : String,
+    pub post_time : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum LegacyContents {
+    TwitterContent(LegacyTwitterContent),
+    OffChainContent(OffChainContent),
+    Custom(CustomContent),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyStorage {
+    pub owner : String,
+    pub uuid : String,
+    pub content_type : ContentType,
+    pub content : LegacyContents,
+    pub d_platform : String,
+    pub is_minted : bool,
+    pub mint_time : String,
+    pub upload_time : String,
+}
+
+// 更早版本按类型分开存储
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TwitterStorage {
+    pub owner : String,
+    pub uuid : String,
+    pub twitter_content : LegacyTwitterContent,
+    pub d_platform : String,
+    pub is_minted : bool,
+    pub mint_time : String,
+    pub upload_time : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct OffStorage {
+    pub owner : String,
+    pub uuid : String,
+    pub off_content : OffChainContent,
+    pub d_platform : String,
+    pub is_minted : bool,
+    pub mint_time : String,
+    pub upload_time : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyStableState {
+    pub pub_key : String,
+    pub name : String,
+    pub main_id : LegacyID,
+    pub ids : Vec<LegacyID>,
+    pub ic_pending : Option<BTreeMap<String, IcChallenge>>,
+    pub delegates : Option<BTreeMap<String, Delegate>>,
+    pub pending_owner : Option<OwnerTransfer>,
+    pub recovery_config : Option<RecoveryConfig>,
+    pub recovery : Option<RecoveryRequest>,
+    pub avatar_url : String,
+    pub avatar : Avatar,
+    pub twitter_store : BTreeMap<String, TwitterStorage>,
+    pub off_store : BTreeMap<String, OffStorage>,
+    pub stores : Option<BTreeMap<ContentType, BTreeMap<String, LegacyStorage>>>,
+    pub schemas : Option<BTreeMap<String, ContentSchema>>,
+}
+
+impl LegacyID {
+    fn migrate(self) -> ID {
+        ID {
+            platform: self.platform,
+            identity: self.identity,
+            bind_time: timestamp::parse(&self.bind_time).unwrap_or(0),
+        }
+    }
+}
+
+impl LegacyTwitterContent {
+    fn migrate(self) -> TwitterContent {
+        TwitterContent {
+            url: self.url,
+            text_content: self.text_content,
+            text_url: self.text_url,
+            image_urls: self.image_urls,
+            video_url: self.video_url,
+            post_time: timestamp::parse(&self.post_time).unwrap_or(0),
+        }
+    }
+}
+
+impl LegacyContents {
+    fn migrate(self) -> Contents {
+        match self {
+            LegacyContents::TwitterContent(t) => Contents::TwitterContent(t.migrate()),
+            LegacyContents::OffChainContent(o) => Contents::OffChainContent(o),
+            LegacyContents::Custom(c) => Contents::Custom(c),
+        }
+    }
+}
+
+impl LegacyStorage {
+    fn migrate(self) -> Storage {
+        Storage {
+            owner: self.owner,
+            uuid: self.uuid,
+            content_type: self.content_type,
+            content: self.content.migrate(),
+            d_platform: self.d_platform,
+            is_minted: self.is_minted,
+            mint_time: timestamp::parse(&self.mint_time),
+            upload_time: timestamp::parse(&self.upload_time).unwrap_or(0),
+            edit_time: None,
+        }
+    }
+}
+
+impl TwitterStorage {
+    fn migrate(self) -> LegacyStorage {
+        LegacyStorage {
+            owner: self.owner,
+            uuid: self.uuid,
+            content_type: ContentType::Twitter,
+            content: LegacyContents::TwitterContent(self.twitter_content),
+            d_platform: self.d_platform,
+            is_minted: self.is_minted,
+            mint_time: self.mint_time,
+            upload_time: self.upload_time,
+        }
+    }
+}
+
+impl OffStorage {
+    fn migrate(self) -> LegacyStorage {
+        LegacyStorage {
+            owner: self.owner,
+            uuid: self.uuid,
+            content_type: ContentType::OffChain,
+            content: LegacyContents::OffChainContent(self.off_content),
+            d_platform: self.d_platform,
+            is_minted: self.is_minted,
+            mint_time: self.mint_time,
+            upload_time: self.upload_time,
+        }
+    }
+}
+
+impl LegacyStableState {
+    pub fn migrate(self) -> StableState {
+        let mut stores = self.stores.unwrap_or_default();
+        stores.entry(ContentType::Twitter).or_default()
+            .extend(self.twitter_store.into_iter().map(|(k, ts)| (k, ts.migrate())));
+        stores.entry(ContentType::OffChain).or_default()
+            .extend(self.off_store.into_iter().map(|(k, os)| (k, os.migrate())));
+        StableState {
+            pub_key: self.pub_key,
+            name: self.name,
+            main_id: self.main_id.migrate(),
+            ids: self.ids.into_iter().map(LegacyID::migrate).collect::<BTreeSet<ID>>(),
+            ic_pending: self.ic_pending,
+            delegates: self.delegates,
+            pending_owner: self.pending_owner,
+            recovery_config: self.recovery_config,
+            recovery: self.recovery,
+            avatar_url: self.avatar_url,
+            avatar: self.avatar,
+            stores: Some(stores.into_iter().map(|(content_type, store)| {
+                (content_type, store.into_iter().map(|(k, item)| (k, item.migrate())).collect())
+            }).collect()),
+            schemas: self.schemas,
+            proofs: None,
+            validity: None,
+            revoked: None,
+            stale_notified: None,
+            visibility: None,
+            presentations: None,
+            main_id_policy: None,
+            main_id_time: None,
+            tags: None,
+            collections: None,
+            versions: None,
+            trash: None,
+            media: None,
+        }
+    }
+}
@@ -2,15 +2,45 @@ pub mod types;
 pub mod verify;
 pub mod http;
 pub mod rc_bytes;
+pub mod schema;
+pub mod timestamp;
+pub mod legacy;
+pub mod search;
+pub mod history;
+pub mod media;
+pub mod certify;
+pub mod api;
+pub mod pages;
+pub mod feed;
+pub mod activitypub;
+pub mod did;
+pub mod credential;
+pub mod disclosure;
+pub mod recovery;
+pub mod timer;
 
-use std::ptr::null;
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::ops::Bound;
 use types::{Xid, Contents, StoreArg, ContentType,
-            TwitterContent, OffChainContent, XidResponse,
-            TwitterStorage, OffStorage, ContentUuid
+            XidResponse,
+            ContentUuid, ContentSchema
             , XidArgs, Avatar, State, XidError, SimpleId,
-            StableState, ID, XidCenterError, Storage};
-use verify::{Payload, VerifyError, MsgIn};
-use http::{HttpRequest, HttpResponse, build_404, build_202};
+            StableState, ID, XidCenterError, Storage, IcChallenge,
+            Scope, Delegate, DelegateArgs, OwnerTransfer,
+            Guardian, RecoveryConfig, RecoveryRequest,
+            ListArgs, SortOrder, StorePage, TimelineKey, TimeRange, SearchArgs, DidDocument,
+            CredentialArgs, PreparedCredential, IssuedCredential, BindingProof, ProofMethod,
+            IdState, IdStatus, IdVisibility, MainIdPolicy, MainIdUnbind, Presentation, PresentationArgs, PresentedIds, DisclosedId,
+            Collection, CollectionArgs, CollectionInfo, StoreVersion,
+            TrashEntry, TrashPage, MediaInfo, UploadSession,
+            CreateUploadArgs, CommitUploadArgs};
+use legacy::LegacyStableState;
+use disclosure::simple_id;
+use verify::{Payload, VerifyError, MsgIn, DelegationIn};
+use http::{HttpRequest, HttpResponse, StreamingCallbackToken, StreamingCallbackHttpResponse,
+           build_404, build_202, build_redirect, serve_document, is_certifiable, build_media, build_chunk, build_chunk_end, token_index};
+use serde_bytes::ByteBuf;
 use candid::{candid_method, Principal};
 use ic_kit::{ic};
 use ic_cdk::{caller};
@@ -20,13 +50,31 @@ thread_local! {
     static STATE : State = State::default();
 }
 pub const VERSION : u8 = 0;
+pub const IC_VERIFY_TTL : u64 = 60 * 60 * 1_000_000_000;
+pub const MAX_IC_PENDING : usize = 50;
+pub const MAX_IC_PENDING_PER_CALLER : usize = 10;
+pub const MAX_PAGE_SIZE : u64 = 100;
+pub const MAX_TAG_LEN : usize = 32;
+pub const MAX_TAGS : usize = 1000;
+pub const MAX_DESCRIPTION_LEN : usize = 1024;
+pub const MAX_COLLECTIONS : usize = 100;
+pub const MAX_COLLECTION_ITEMS : usize = 1000;
+pub const TRASH_RETENTION : u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+pub const PURGE_INTERVAL : u64 = 60 * 60 * 1_000_000_000;
+pub const PURGE_BATCH : usize = 100;
+pub const MIN_VALIDITY_PERIOD : u64 = 24 * 60 * 60 * 1_000_000_000;
+pub const MAX_PLATFORM_LEN : usize = 32;
+pub const STALE_NOTIFY_BATCH : usize = 20;
+pub const MAX_MAIN_ID_PLATFORMS : usize = 20;
+pub const MAX_MAIN_ID_COOLDOWN : u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
 
 #[init]
 #[candid_method(init)]
 fn init(arg : Principal) {
     STATE.with(|s| {
         *s.pub_key.borrow_mut() = Principal::to_text(&arg);
-    })
+    });
+    timer::schedule(ic_cdk::api::time());
 }
 
 #[query(name = "getVersion")]
@@ -44,22 +92,289 @@ fn get_cycle_balance() -> u64 {
 #[query(name = "getXid")]
 #[candid_method(query, rename = "getXid")]
 fn get_xid() -> Xid {
+    let full = can_manage_identity().is_ok();
     STATE.with(|s| {
         Xid {
             pub_key: s.pub_key.borrow().clone(),
             name: s.name.borrow().clone(),
-            main_id: s.main_id.borrow().clone(),
-            ids: s.ids.borrow().iter().map(|l| l.clone()).collect(),
+            main_id: visible_main_id(s, full),
+            ids: s.ids.borrow().iter().filter(|l| can_view(s, full, &simple_id(l))).cloned().collect(),
             avatar_url: s.avatar_url.borrow().clone(),
+            statuses: id_states(s, ic_cdk::api::time()).into_iter()
+                .filter(|st| match st.status {
+                    IdStatus::Revoked => full,
+                    _ => can_view(s, full, &st.id),
+                })
+                .collect(),
+        }
+    })
+}
+
+// 调用者可见的身份, full为调用者有IdentityManage权限
+fn can_view(s : &State, full : bool, id : &SimpleId) -> bool {
+    full || disclosure::is_visible(s, id, Some(&caller()))
+}
+
+// 身份守护者受身份可见性约束, 不可见的不返回
+fn visible_guardians(s : &State, full : bool, guardians : &mut Vec<Guardian>) {
+    guardians.retain(|g| match g {
+        Guardian::Principal(_) => true,
+        Guardian::Identity(id) => can_view(s, full, id),
+    });
+}
+
+// 主身份对调用者不可见时返回空身份
+fn visible_main_id(s : &State, full : bool) -> ID {
+    let main_id = s.main_id.borrow();
+    if can_view(s, full, &simple_id(&main_id)) { main_id.clone() } else { ID::default() }
+}
+
+// 身份的过期时间, 平台未设置有效期时为None
+fn expire_time(s : &State, id : &ID) -> Option<u64> {
+    s.validity.borrow().get(&id.platform).map(|period| id.bind_time.saturating_add(*period))
+}
+
+fn is_stale(s : &State, id : &ID, now : u64) -> bool {
+    expire_time(s, id).is_some_and(|t| t <= now)
+}
+
+// 已绑定身份的状态, 其后为已解绑身份的记录
+fn id_states(s : &State, now : u64) -> Vec<IdState> {
+    let mut states : Vec<IdState> = s.ids.borrow().iter().map(|id| IdState {
+        id: SimpleId {
+            platform: id.platform.clone(),
+            identity: id.identity.clone(),
+        },
+        status: if is_stale(s, id, now) { IdStatus::Stale } else { IdStatus::Active },
+        bind_time: id.bind_time,
+        expire_time: expire_time(s, id),
+        revoke_time: None,
+    }).collect();
+    states.extend(s.revoked.borrow().values().cloned());
+    states
+}
+
+// 设置平台的绑定有效期, 超期未重新验证的身份标记为过期; None表示永久有效
+#[update(name = "setValidityPeriod", guard="can_manage_identity")]
+#[candid_method(update, rename = "setValidityPeriod")]
+async fn set_validity_period(platform : String, period : Option<u64>) -> Result<XidResponse, XidError> {
+    if platform.is_empty() || platform.len() > MAX_PLATFORM_LEN {
+        return Err(XidError::InvalidName);
+    };
+    STATE.with(|s| {
+        let mut validity = s.validity.borrow_mut();
+        match period {
+            Some(p) if p < MIN_VALIDITY_PERIOD => return Err(XidError::FieldOutOfRange),
+            Some(p) => { validity.insert(platform, p); },
+            None => { validity.remove(&platform); },
+        };
+        Ok(XidResponse::ConfigOk)
+    })
+}
+
+#[query(name = "getValidityPolicy")]
+#[candid_method(query, rename = "getValidityPolicy")]
+fn get_validity_policy() -> Vec<(String, u64)> {
+    STATE.with(|s| {
+        s.validity.borrow().iter().map(|(platform, period)| (platform.clone(), *period)).collect()
+    })
+}
+
+// did:icp:<canister id>的DID文档, 与/.well-known/did.json一致
+#[query(name = "getDidDocument")]
+#[candid_method(query, rename = "getDidDocument")]
+fn get_did_document() -> DidDocument {
+    STATE.with(|s| did::document(s, ic_cdk::id()))
+}
+
+// 为已绑定身份签发可验证凭证, 需在签名过期前以返回值调用getCredential
+#[update(name = "prepareCredential", guard="is_authorized")]
+#[candid_method(update, rename = "prepareCredential")]
+async fn prepare_credential(arg : CredentialArgs) -> Result<PreparedCredential, XidError> {
+    let now = ic_cdk::api::time();
+    let prepared = STATE.with(|s| {
+        s.ids.borrow().iter()
+            .find(|id| id.platform == arg.platform && id.identity == arg.identity)
+            .ok_or(XidError::IDNotExist)
+            .and_then(|id| {
+                if is_stale(s, id, now) {
+                    return Err(XidError::IdStale);
+                };
+                let proof = s.proofs.borrow().get(&SimpleId {
+                    platform: id.platform.clone(),
+                    identity: id.identity.clone(),
+                }).cloned();
+                Ok(credential::prepare(id, proof.as_ref(), arg.format, ic_cdk::id(), now))
+            })
+    })?;
+    certify::add_signature(credential::SIG_SEED, credential::message_hash(&prepared.signing_input), now);
+    Ok(prepared)
+}
+
+#[query(name = "getCredential")]
+#[candid_method(query, rename = "getCredential")]
+fn get_credential(arg : PreparedCredential) -> Result<IssuedCredential, XidError> {
+    let signature = certify::signature(credential::SIG_SEED, credential::message_hash(&arg.signing_input))
+        .ok_or(XidError::CredentialNotExist)?;
+    Ok(credential::assemble(arg, &signature, ic_cdk::id()))
+}
+
+// 身份绑定时的原始证明, 更早绑定的身份没有记录
+#[query(name = "getBindingProof")]
+#[candid_method(query, rename = "getBindingProof")]
+fn get_binding_proof(arg : SimpleId) -> Option<BindingProof> {
+    let full = can_manage_identity().is_ok();
+    STATE.with(|s| {
+        if !can_view(s, full, &arg) {
+            return None;
+        };
+        s.proofs.borrow().get(&arg).cloned()
+    })
+}
+
+// 设置身份的可见范围, 不影响已生成的披露
+#[update(name = "setIdVisibility", guard="can_manage_identity")]
+#[candid_method(update, rename = "setIdVisibility")]
+async fn set_id_visibility(id : SimpleId, visibility : IdVisibility) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    STATE.with(|s| {
+        if !is_bound(&s.ids.borrow(), &id) {
+            return Err(XidError::IDNotExist);
+        };
+        let mut map = s.visibility.borrow_mut();
+        match visibility {
+            IdVisibility::Public => { map.remove(&id); },
+            IdVisibility::Shared(principals) if principals.len() > disclosure::MAX_SHARED => {
+                return Err(XidError::FieldOutOfRange);
+            },
+            v => { map.insert(id, v); },
+        };
+        Ok(XidResponse::ConfigOk)
+    })
+}
+
+#[query(name = "getIdVisibility", guard="can_manage_identity")]
+#[candid_method(query, rename = "getIdVisibility")]
+fn get_id_visibility() -> Vec<(SimpleId, IdVisibility)> {
+    STATE.with(|s| {
+        s.visibility.borrow().iter().map(|(id, v)| (id.clone(), v.clone())).collect()
+    })
+}
+
+// 为验证方生成限时披露, 所选身份不受可见范围限制
+#[update(name = "createPresentation", guard="can_manage_identity")]
+#[candid_method(update, rename = "createPresentation")]
+async fn create_presentation(arg : PresentationArgs) -> Result<Presentation, XidError> {
+    let now = ic_cdk::api::time();
+    if arg.ttl == 0 || arg.ttl > disclosure::MAX_PRESENTATION_TTL {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let mut ids = arg.ids;
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() || ids.len() > disclosure::MAX_PRESENTATION_IDS {
+        return Err(XidError::FieldOutOfRange);
+    };
+    STATE.with(|s| {
+        if ids.iter().any(|id| !is_bound(&s.ids.borrow(), id)) {
+            return Err(XidError::IDNotExist);
+        };
+        let mut presentations = s.presentations.borrow_mut();
+        presentations.retain(|_, p| !disclosure::is_expired(p, now));
+        if presentations.len() >= disclosure::MAX_PRESENTATIONS {
+            return Err(XidError::FieldOutOfRange);
+        };
+        let presentation = Presentation {
+            token: disclosure::token(&arg.verifier, &ids, now),
+            verifier: arg.verifier,
+            ids,
+            create_time: now,
+            expire_time: now.saturating_add(arg.ttl),
+        };
+        presentations.insert(presentation.token.clone(), presentation.clone());
+        Ok(presentation)
+    })
+}
+
+#[update(name = "revokePresentation", guard="can_manage_identity")]
+#[candid_method(update, rename = "revokePresentation")]
+async fn revoke_presentation(token : String) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.presentations.borrow_mut().remove(&token) {
+            Some(_) => Ok(XidResponse::RevokeOk),
+            None => Err(XidError::PresentationNotExist),
         }
     })
 }
 
+#[query(name = "getPresentations", guard="can_manage_identity")]
+#[candid_method(query, rename = "getPresentations")]
+fn get_presentations() -> Vec<Presentation> {
+    STATE.with(|s| {
+        s.presentations.borrow().values().cloned().collect()
+    })
+}
+
+// 仅指定的验证方可读取, 返回所选身份中仍绑定者及其证明
+#[query(name = "getPresentation")]
+#[candid_method(query, rename = "getPresentation")]
+fn get_presentation(token : String) -> Result<PresentedIds, XidError> {
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        let presentations = s.presentations.borrow();
+        let presentation = match presentations.get(&token) {
+            Some(p) if p.verifier == caller() => p,
+            _ => return Err(XidError::PresentationNotExist),
+        };
+        if disclosure::is_expired(presentation, now) {
+            return Err(XidError::PresentationExpired);
+        };
+        let bound = s.ids.borrow();
+        let proofs = s.proofs.borrow();
+        let ids = presentation.ids.iter()
+            .filter_map(|simple| {
+                let id = bound.get(&ID {
+                    platform: simple.platform.clone(),
+                    identity: simple.identity.clone(),
+                    bind_time: 0,
+                })?;
+                Some(DisclosedId {
+                    id: id.clone(),
+                    status: if is_stale(s, id, now) { IdStatus::Stale } else { IdStatus::Active },
+                    proof: proofs.get(simple).cloned(),
+                })
+            })
+            .collect();
+        Ok(PresentedIds {
+            xid: ic_cdk::id(),
+            ids,
+            expire_time: presentation.expire_time,
+        })
+    })
+}
+
 #[query(name = "getMainId")]
 #[candid_method(query, rename = "getMainId")]
 fn get_main_id() -> ID {
+    let full = can_manage_identity().is_ok();
     STATE.with(|s|{
-        s.main_id.borrow().clone()
+        visible_main_id(s, full)
+    })
+}
+
+// 按绑定时间升序返回区间内的身份
+#[query(name = "getIds")]
+#[candid_method(query, rename = "getIds")]
+fn get_ids(range : Option<TimeRange>) -> Vec<ID> {
+    let range = range.unwrap_or_default();
+    let full = can_manage_identity().is_ok();
+    STATE.with(|s| {
+        let mut ids : Vec<ID> = s.ids.borrow().iter()
+            .filter(|id| range.contains(id.bind_time) && can_view(s, full, &simple_id(id)))
+            .cloned()
+            .collect();
+        ids.sort_by_key(|id| id.bind_time);
+        ids
     })
 }
 
@@ -67,192 +382,435 @@ fn get_main_id() -> ID {
 #[candid_method(query, rename = "getStoreSize")]
 fn get_store_size(arg : ContentType) -> usize {
     STATE.with(|s| {
-        return match arg {
-            ContentType::Twitter => {
-                s.twitter_store.borrow().len()
-            },
-            ContentType::OffChain => {
-                s.off_store.borrow().len()
-            }
-        }
+        s.stores.borrow().get(&arg).map_or(0, |store| store.len())
     })
 }
 
 #[query(name = "getStoreList")]
 #[candid_method(query, rename = "getStoreList")]
 fn get_store_list(arg : ContentType, start : usize, offset : usize) -> Result<Vec<Storage>, XidError> {
-    STATE.with(|s|{
-        return match arg {
-            ContentType::Twitter => {
-                if start >= s.twitter_store.borrow().len() {
-                    Err(XidError::FieldOutOfRange)
-                } else {
-                    let mut tmp_offset : usize = 0;
-                    if start + offset > s.twitter_store.borrow().len() {
-                        tmp_offset = s.twitter_store.borrow().len() - start;
-                    } else {
-                        tmp_offset = offset;
-                    };
-                    let mut st : usize = 0;
-                    let mut res : Vec<Storage>  = Vec::new();
-                    let twitter_store = s.twitter_store.borrow();
-                    let twitter_vec  =
-                        twitter_store.
-                            values();
-                    for ts in twitter_vec {
-                        if st >= start && st < (start + tmp_offset) {
-                            res.push(Storage{
-                                owner: ts.owner.clone(),
-                                uuid: ts.uuid.clone(),
-                                content: Contents::TwitterContent(ts.twitter_content.clone()),
-                                d_platform: ts.d_platform.clone(),
-                                is_minted: ts.is_minted.clone(),
-                                mint_time: ts.mint_time.clone(),
-                                upload_time: ts.upload_time.clone(),
-                            })
-                        };
-                        st += 1;
-                    }
-                    Ok(res)
-                }
-            },
-            ContentType::OffChain => {
-                if start >= s.off_store.borrow().len() {
-                    Err(XidError::FieldOutOfRange)
-                } else {
-                    let mut tmp_offset : usize = 0;
-                    if start + offset > s.twitter_store.borrow().len() {
-                        tmp_offset = s.twitter_store.borrow().len() - start;
-                    } else {
-                        tmp_offset = offset;
-                    };
-                    let mut st : usize = 0;
-                    let mut res : Vec<Storage>  = Vec::new();
-                    let off_store = s.off_store.borrow();
-                    let off_vec =
-                        off_store.
-                            values();
-                    for os in off_vec {
-                        if st >= start && st < (start + tmp_offset) {
-                            res.push(Storage{
-                                owner: os.owner.clone(),
-                                uuid: os.uuid.clone(),
-                                content: Contents::OffChainContent(os.off_content.clone()),
-                                d_platform: os.d_platform.clone(),
-                                is_minted: os.is_minted.clone(),
-                                mint_time: os.mint_time.clone(),
-                                upload_time: os.upload_time.clone(),
-                            })
-                        };
-                        st += 1;
-                    }
-                    Ok(res)
-                }
-            }
-        }
+    STATE.with(|s| Ok(store_slice(s, &arg, start, offset)))
+}
+
+// 超出末尾或类型不存在时为空
+fn store_slice(s : &State, content_type : &ContentType, start : usize, offset : usize) -> Vec<Storage> {
+    match s.stores.borrow().get(content_type) {
+        Some(store) => store.values().skip(start).take(offset).cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+// 游标分页, cursor为上一页返回的next_cursor
+#[query(name = "listStore")]
+#[candid_method(query, rename = "listStore")]
+fn list_store(arg : ListArgs) -> Result<StorePage, XidError> {
+    STATE.with(|s| list_items(s, arg))
+}
+
+// 已到末尾时返回空页, next_cursor为None
+fn list_items(s : &State, arg : ListArgs) -> Result<StorePage, XidError> {
+    if arg.limit == 0 || arg.limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let cursor = match &arg.cursor {
+        Some(c) => match from_hex(c).and_then(|b| String::from_utf8(b).ok()) {
+            Some(uuid) => Bound::Excluded(uuid),
+            None => return Err(XidError::FieldOutOfRange),
+        },
+        None => Bound::Unbounded,
+    };
+    let range = match arg.order {
+        SortOrder::Asc => (cursor, Bound::Unbounded),
+        SortOrder::Desc => (Bound::Unbounded, cursor),
+    };
+    let stores = s.stores.borrow();
+    let store = match stores.get(&arg.content_type) {
+        Some(store) => store,
+        None => return Ok(StorePage { items: Vec::new(), next_cursor: None }),
+    };
+    let iter = store.range(range).map(|(_, item)| item);
+    let iter : Box<dyn Iterator<Item = &Storage>> = match arg.order {
+        SortOrder::Asc => Box::new(iter),
+        SortOrder::Desc => Box::new(iter.rev()),
+    };
+    let items : Vec<Storage> = iter
+        .filter(|item| arg.filter.matches(item))
+        .take(arg.limit as usize)
+        .cloned()
+        .collect();
+    let next_cursor = if items.len() as u64 == arg.limit {
+        items.last().map(|item| to_hex(item.uuid.as_bytes()))
+    } else {
+        None
+    };
+    Ok(StorePage { items, next_cursor })
+}
+
+// 推文与链下内容按时间倒序合并, cursor为上一页返回的next_cursor
+#[query(name = "getTimeline")]
+#[candid_method(query, rename = "getTimeline")]
+fn get_timeline(cursor : Option<String>, limit : u64, range : Option<TimeRange>) -> Result<StorePage, XidError> {
+    STATE.with(|s| timeline_page(s, cursor, limit, range))
+}
+
+// 时间相同的内容按(类型, uuid)排序, 游标为上一页最后一条的完整key
+fn timeline_page(s : &State, cursor : Option<String>, limit : u64, range : Option<TimeRange>) -> Result<StorePage, XidError> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let range = range.unwrap_or_default();
+    let mut upper = match &cursor {
+        Some(c) => match decode_timeline_cursor(c) {
+            Some(key) => Bound::Excluded(key),
+            None => return Err(XidError::FieldOutOfRange),
+        },
+        None => Bound::Unbounded,
+    };
+    // 时间区间的上界与游标取较小者
+    if let Some(to) = range.to.and_then(|t| t.checked_add(1)) {
+        let cap = (to, ContentType::Twitter, String::new());
+        if !matches!(&upper, Bound::Excluded(key) if *key < cap) {
+            upper = Bound::Excluded(cap);
+        };
+    };
+    let lower = match range.from {
+        Some(from) => (from, ContentType::Twitter, String::new()),
+        None => (0, ContentType::Twitter, String::new()),
+    };
+    if matches!(&upper, Bound::Excluded(key) if *key < lower) {
+        return Ok(StorePage { items: Vec::new(), next_cursor: None });
+    };
+    let timeline = s.timeline.borrow();
+    let stores = s.stores.borrow();
+    let keys : Vec<&TimelineKey> = timeline
+        .range((Bound::Included(lower), upper))
+        .rev()
+        .take(limit as usize)
+        .collect();
+    let items : Vec<Storage> = keys.iter().filter_map(|(_, content_type, uuid)| {
+        stores.get(content_type).and_then(|store| store.get(uuid)).cloned()
+    }).collect();
+    let next_cursor = if keys.len() as u64 == limit {
+        keys.last().map(|key| encode_timeline_cursor(key))
+    } else {
+        None
+    };
+    Ok(StorePage { items, next_cursor })
+}
+
+// 全文检索, 结果按(类型, uuid)排序分页
+#[query(name = "search")]
+#[candid_method(query, rename = "search")]
+fn search(arg : SearchArgs) -> Result<StorePage, XidError> {
+    if arg.limit == 0 || arg.limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let terms = match search::query_terms(&arg.query) {
+        Some(terms) => terms,
+        None => return Err(XidError::FieldOutOfRange),
+    };
+    let after = match &arg.cursor {
+        Some(c) => match decode_content_cursor(c) {
+            Some(key) => Some(key),
+            None => return Err(XidError::FieldOutOfRange),
+        },
+        None => None,
+    };
+    STATE.with(|s| {
+        let hits = s.search_index.borrow().search(&terms);
+        let keys : Vec<&ContentUuid> = hits.iter()
+            .filter(|key| after.as_ref().is_none_or(|a| *key > a))
+            .filter(|key| arg.content_type.as_ref().is_none_or(|t| key.content_type == *t))
+            .take(arg.limit as usize)
+            .collect();
+        Ok(build_page(&s.stores.borrow(), &keys, arg.limit))
+    })
+}
+
+fn encode_content_cursor(key : &ContentUuid) -> String {
+    to_hex(format!("{}/{}", key.content_type.name(), key.uuid).as_bytes())
+}
+
+fn decode_content_cursor(cursor : &str) -> Option<ContentUuid> {
+    let raw = String::from_utf8(from_hex(cursor)?).ok()?;
+    let (name, uuid) = raw.split_once('/')?;
+    Some(ContentUuid {
+        content_type: ContentType::from_name(name),
+        uuid: uuid.to_string(),
     })
 }
 
+fn encode_timeline_cursor(key : &TimelineKey) -> String {
+    to_hex(format!("{}/{}/{}", key.0, key.1.name(), key.2).as_bytes())
+}
+
+fn decode_timeline_cursor(cursor : &str) -> Option<TimelineKey> {
+    let raw = String::from_utf8(from_hex(cursor)?).ok()?;
+    let mut parts = raw.splitn(3, '/');
+    let time = parts.next()?.parse::<u64>().ok()?;
+    let content_type = ContentType::from_name(parts.next()?);
+    Some((time, content_type, parts.next()?.to_string()))
+}
+
+// 重建不持久化的索引
+fn rebuild_indexes() {
+    STATE.with(|s| {
+        rebuild_content_indexes(s);
+        certify::clear();
+        certify_avatar(&s.avatar.borrow());
+        for asset in s.media.borrow().values() {
+            certify::put(&media::media_path(&asset.key), media::certified_hash(asset));
+        }
+    });
+    refresh_pages();
+}
+
+// 时间线与全文索引由stores重建
+fn rebuild_content_indexes(s : &State) {
+    let mut timeline = s.timeline.borrow_mut();
+    let mut search_index = s.search_index.borrow_mut();
+    timeline.clear();
+    search_index.clear();
+    for store in s.stores.borrow().values() {
+        timeline.extend(store.values().map(|item| item.timeline_key()));
+        store.values().for_each(|item| search_index.insert(item));
+    }
+}
+
+// 重新渲染公开页面, 订阅源, ActivityPub与DID文档并更新证书树
+fn refresh_pages() {
+    let canister = ic_cdk::id();
+    let base = pages::public_base(canister);
+    let now = ic_cdk::api::time();
+    let (documents, objects) = STATE.with(|s| {
+        let profile = pages::render_profile(s, &base);
+        (vec![
+            (pages::PROFILE_PATHS[0], pages::HTML_TYPE, profile.clone()),
+            (pages::PROFILE_PATHS[1], pages::HTML_TYPE, profile),
+            (feed::RSS_PATH, feed::RSS_TYPE, feed::render_rss(s, &base)),
+            (feed::ATOM_PATH, feed::ATOM_TYPE, feed::render_atom(s, &base)),
+            (activitypub::WEBFINGER_PATH, activitypub::JRD_TYPE, activitypub::render_webfinger(&base)),
+            (activitypub::ACTOR_PATH, activitypub::ACTIVITY_TYPE, activitypub::render_actor(s, &base, canister)),
+            (activitypub::OUTBOX_PATH, activitypub::ACTIVITY_TYPE, activitypub::render_outbox(s, &base)),
+            (activitypub::FOLLOWERS_PATH, activitypub::ACTIVITY_TYPE,
+             activitypub::render_empty_collection(&base, activitypub::FOLLOWERS_PATH)),
+            (activitypub::FOLLOWING_PATH, activitypub::ACTIVITY_TYPE,
+             activitypub::render_empty_collection(&base, activitypub::FOLLOWING_PATH)),
+            // 投递不做处理, POST同样返回证书认证的空集合
+            (activitypub::INBOX_PATH, activitypub::ACTIVITY_TYPE,
+             activitypub::render_empty_collection(&base, activitypub::INBOX_PATH)),
+            (did::DID_PATH, did::DID_TYPE, did::render(s, canister)),
+        ], activitypub::render_objects(s, &base))
+    });
+    for (path, content_type, body) in documents {
+        pages::set(path, content_type, body, now);
+    }
+    pages::replace_prefix(activitypub::OBJECTS_PREFIX, activitypub::ACTIVITY_TYPE, objects, now);
+}
+
+fn certify_avatar(avatar : &Avatar) {
+    if avatar.image_data.is_empty() {
+        certify::remove(certify::AVATAR_PATH);
+    } else {
+        certify::put(certify::AVATAR_PATH, certify::hash(&avatar.image_data));
+    };
+}
+
 #[query(name = "getStoreByUuid")]
 #[candid_method(query, rename = "getStoreByUuid")]
 fn get_store_by_uuid(arg : Vec<ContentUuid>) -> Vec<Storage> {
-    let mut res : Vec<Storage> = Vec::new();
-    STATE.with(|s| {
-        let twitter_store = s.twitter_store.borrow();
-        let off_store = s.off_store.borrow();
-        for ts in arg {
-            match ts.content_type {
-                ContentType::Twitter => {
-                    match twitter_store.get(&ts.uuid) {
-                        Some(store) => {
-                            res.push(Storage{
-                                owner: store.owner.clone(),
-                                uuid: store.uuid.clone(),
-                                content: Contents::TwitterContent(store.twitter_content.clone()),
-                                d_platform: store.d_platform.clone(),
-                                is_minted: store.is_minted.clone(),
-                                mint_time: store.mint_time.clone(),
-                                upload_time: store.upload_time.clone(),
-                            })
-                        },
-                        None => {},
-                    };
-                },
-                ContentType::OffChain => {
-                    match off_store.get(&ts.uuid) {
-                        Some(store) => {
-                            res.push(Storage{
-                                owner: store.owner.clone(),
-                                uuid: store.uuid.clone(),
-                                content: Contents::OffChainContent(store.off_content.clone()),
-                                d_platform: store.d_platform.clone(),
-                                is_minted: store.is_minted.clone(),
-                                mint_time: store.mint_time.clone(),
-                                upload_time: store.upload_time.clone(),
-                            })
-                        },
-                        None => {},
-                    };
-                },
-            };
-        };
+    STATE.with(|s| {
+        let stores = s.stores.borrow();
+        arg.iter().filter_map(|key| {
+            stores.get(&key.content_type).and_then(|store| store.get(&key.uuid)).cloned()
+        }).collect()
+    })
+}
 
-    });
-    res
+#[query(name = "getContentTypes")]
+#[candid_method(query, rename = "getContentTypes")]
+fn get_content_types() -> Vec<ContentSchema> {
+    STATE.with(|s| {
+        s.schemas.borrow().values().cloned().collect()
+    })
 }
 
 #[query(name = "http_request")]
 #[candid_method(query, rename = "http_request")]
 fn http_request(request : HttpRequest) -> HttpResponse {
-    STATE.with(|s| {
-        let path = request.url.split_terminator("/").collect::<Vec<&str>>();
-        if path.len() == 3 {
-            if path[1] == "avatar" {
-                let avatar = s.avatar.borrow().clone();
-                return build_202(avatar.image_data, avatar.image_type);
-            }
+    let url = request.url.split('?').next().unwrap_or_default();
+    if api::is_api_path(url) {
+        return api::handle(&request, url);
+    };
+    let response = STATE.with(|s| {
+        if let Some(key) = url.strip_prefix("/media/") {
+            return match s.media.borrow().get(key) {
+                Some(asset) => build_media(asset, ic_cdk::id()),
+                None => build_404(),
+            };
+        };
+        if url == certify::AVATAR_PATH {
+            let avatar = s.avatar.borrow().clone();
+            return build_202(avatar.image_data, avatar.image_type);
+        };
+        // 证书按路径认证, WebFinger仅在resource指向本xid时返回预渲染的文档
+        if url == activitypub::WEBFINGER_PATH {
+            let resource = api::parse_query(&request.url).remove("resource").unwrap_or_default();
+            if !activitypub::webfinger_matches(&pages::public_base(ic_cdk::id()), &resource) {
+                return build_404();
+            };
+        };
+        if let Some(doc) = pages::get(url) {
+            return serve_document(&doc, &request);
+        };
+        // 旧版 /avatar/<任意> 路径重定向到认证的/avatar
+        let path = url.split_terminator("/").collect::<Vec<&str>>();
+        if path.len() == 3 && path[1] == "avatar" {
+            return match pages::get(certify::FALLBACK_PATH) {
+                Some(doc) => build_redirect(certify::AVATAR_PATH, &doc),
+                None => build_404(),
+            };
         }
         build_404()
-    })
+    });
+    match certify::certificate_header(url).filter(|_| is_certifiable(&response)) {
+        Some(header) => response.with_header(header),
+        None => response,
+    }
 }
 
-#[update(name = "changeMainId", guard="is_authorized")]
+#[update(name = "changeMainId", guard="can_manage_identity")]
 #[candid_method(update, rename = "changeMainId")]
 async fn change_main_id(arg : ID) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let now = ic_cdk::api::time();
     STATE.with(|s| {
-        if s.ids.borrow().contains(&arg) {
-            *s.main_id.borrow_mut() = arg;
-            Ok(XidResponse::ChangeIdOk)
-        } else {
-            Err(XidError::IDNotExist)
-        }
+        let bound = match s.ids.borrow().get(&arg) {
+            Some(id) => id.clone(),
+            None => return Err(XidError::IDNotExist),
+        };
+        if *s.main_id.borrow() == bound {
+            return Ok(XidResponse::ChangeIdOk);
+        };
+        if !main_id_allowed(s, &bound.platform) || main_id_locked(s, now) {
+            return Err(XidError::MainIdBan);
+        };
+        set_main_id(s, bound, now);
+        Ok(XidResponse::ChangeIdOk)
     })
 }
 
-#[update(name = "unboundId", guard="is_authorized")]
-#[candid_method(update, rename = "unboundId")]
-async fn unbound_id(arg : ID) -> Result<XidResponse, XidError> {
-    let mut flag = Ok(XidResponse::ChangeIdOk);
-    STATE.with(|s| {
-        if s.ids.borrow().contains(&arg) {
-            let mut main_id = s.main_id.borrow_mut();
-            if *main_id == arg {
-                *main_id = ID{
-                    platform: "".to_string(),
-                    identity: "".to_string(),
-                    bind_time: "".to_string(),
-                };
-                let mut ids = s.ids.borrow_mut();
-                let _ = ids.remove(&arg);
-            } else {
-                let mut ids = s.ids.borrow_mut();
-                let _ = ids.remove(&arg);
-            };
-        } else {
-            flag = Err(XidError::IDNotExist);
+// 设置主身份策略, 仅owner可调用; None恢复默认行为
+#[update(name = "setMainIdPolicy", guard="is_authorized")]
+#[candid_method(update, rename = "setMainIdPolicy")]
+async fn set_main_id_policy(arg : Option<MainIdPolicy>) -> Result<XidResponse, XidError> {
+    if let Some(policy) = &arg {
+        if policy.platforms.len() > MAX_MAIN_ID_PLATFORMS || policy.cooldown > MAX_MAIN_ID_COOLDOWN {
+            return Err(XidError::FieldOutOfRange);
+        };
+        if policy.platforms.iter().any(|p| p.is_empty() || p.len() > MAX_PLATFORM_LEN) {
+            return Err(XidError::InvalidName);
         };
+    };
+    STATE.with(|s| {
+        *s.main_id_policy.borrow_mut() = arg;
+        Ok(XidResponse::ConfigOk)
+    })
+}
+
+#[query(name = "getMainIdPolicy")]
+#[candid_method(query, rename = "getMainIdPolicy")]
+fn get_main_id_policy() -> Option<MainIdPolicy> {
+    STATE.with(|s| {
+        s.main_id_policy.borrow().clone()
+    })
+}
+
+// changeMainId主动变更主身份, 开始cooldown
+fn set_main_id(s : &State, id : ID, now : u64) {
+    *s.main_id.borrow_mut() = id;
+    *s.main_id_time.borrow_mut() = now;
+}
+
+// 首次绑定与解绑后的自动选择不开始cooldown, 以免用户被锁定在自动选出的主身份上
+fn assign_main_id(s : &State, id : ID) {
+    *s.main_id.borrow_mut() = id;
+}
+
+// 无主身份时, 策略允许的平台自动成为主身份
+fn assign_first_main_id(s : &State, id : &ID) {
+    if s.main_id.borrow().identity.is_empty() && main_id_allowed(s, &id.platform) {
+        assign_main_id(s, id.clone());
+    };
+}
+
+// 策略未限制平台时任意平台均可作为主身份
+fn main_id_allowed(s : &State, platform : &str) -> bool {
+    s.main_id_policy.borrow().as_ref()
+        .is_none_or(|p| p.platforms.is_empty() || p.platforms.iter().any(|allowed| allowed == platform))
+}
+
+fn main_id_locked(s : &State, now : u64) -> bool {
+    let main_id_time = *s.main_id_time.borrow();
+    s.main_id_policy.borrow().as_ref()
+        .is_some_and(|p| now < main_id_time.saturating_add(p.cooldown))
+}
+
+// 解绑主身份后的继任者: 未过期者优先, 其次按策略的平台顺序, 最后按绑定时间;
+// 未设置策略时不自动选择, 主身份置空, 直到下次绑定或changeMainId
+fn main_id_successor(s : &State, now : u64) -> ID {
+    let policy = s.main_id_policy.borrow();
+    let Some(policy) = policy.as_ref() else {
+        return ID::default();
+    };
+    let priority = |platform : &str| policy.platforms.iter().position(|p| p == platform).unwrap_or(0);
+    s.ids.borrow().iter()
+        .filter(|id| main_id_allowed(s, &id.platform))
+        .min_by_key(|id| (is_stale(s, id, now), priority(&id.platform), id.bind_time))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// 解绑主身份: 策略为Refuse或在cooldown内时拒绝; Promote按main_id_successor选出继任者, 无策略时置空
+fn unbind(s : &State, arg : &ID, now : u64) -> Result<XidResponse, XidError> {
+    let bound = match s.ids.borrow().get(arg) {
+        Some(id) => id.clone(),
+        None => return Err(XidError::IDNotExist),
+    };
+    let is_main = *s.main_id.borrow() == bound;
+    if is_main && (main_id_locked(s, now) || s.main_id_policy.borrow().as_ref()
+        .is_some_and(|p| p.on_unbind == MainIdUnbind::Refuse)) {
+        return Err(XidError::MainIdBan);
+    };
+    let simple_id = SimpleId {
+        platform: arg.platform.clone(),
+        identity: arg.identity.clone(),
+    };
+    s.proofs.borrow_mut().remove(&simple_id);
+    s.stale_notified.borrow_mut().remove(&simple_id);
+    s.visibility.borrow_mut().remove(&simple_id);
+    s.revoked.borrow_mut().insert(simple_id.clone(), IdState {
+        id: simple_id,
+        status: IdStatus::Revoked,
+        bind_time: bound.bind_time,
+        expire_time: None,
+        revoke_time: Some(now),
     });
-    return match flag {
+    s.ids.borrow_mut().remove(arg);
+    if is_main {
+        let successor = main_id_successor(s, now);
+        assign_main_id(s, successor);
+    };
+    Ok(XidResponse::ChangeIdOk)
+}
+
+#[update(name = "unboundId", guard="can_manage_identity")]
+#[candid_method(update, rename = "unboundId")]
+async fn unbound_id(arg : ID) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let now = ic_cdk::api::time();
+    let flag = STATE.with(|s| unbind(s, &arg, now));
+    match flag {
         Ok(ok) => {
             let simple_id = SimpleId{
                 platform: arg.platform,
@@ -285,88 +843,248 @@ async fn unbound_id(arg : ID) -> Result<XidResponse, XidError> {
     }
 }
 
-// xid owner调用约定待绑定ic身份
-#[update(name = "verifyIcPre", guard="is_authorized")]
+// xid owner调用约定待绑定ic身份, 返回的nonce交给待绑定身份调用verifyIcPost
+#[update(name = "verifyIcPre", guard="can_manage_identity")]
 #[candid_method(update, rename = "verifyIcPre")]
-async fn verify_ic_pre(arg : String) -> bool {
+async fn verify_ic_pre(arg : String) -> Result<IcChallenge, VerifyError> {
+    if Principal::from_text(&arg).is_err() {
+        return Err(VerifyError::IcPrincipalErr);
+    };
+    let caller = caller();
+    STATE.with(|s| check_ic_pending(&s.ic_pending.borrow(), &caller, &arg, ic_cdk::api::time()))?;
+    let nonce = match ic::call::<_, (Vec<u8>, ), _>(
+        Principal::management_canister(),
+        "raw_rand",
+        ()
+    ).await {
+        Ok((bytes, )) => to_hex(&bytes),
+        Err(_) => return Err(VerifyError::NonceErr),
+    };
+    let now = ic_cdk::api::time();
+    let challenge = IcChallenge {
+        principal: arg.clone(),
+        nonce,
+        deadline: now + IC_VERIFY_TTL,
+        requester: Some(caller),
+    };
+    STATE.with(|s| {
+        let mut ic_pending = s.ic_pending.borrow_mut();
+        ic_pending.retain(|_, c| c.deadline >= now);
+        // raw_rand期间可能有其他challenge写入, 需再次校验
+        check_ic_pending(&ic_pending, &caller, &arg, now)?;
+        ic_pending.insert(arg, challenge.clone());
+        Ok(challenge)
+    })
+}
+
+// 同一principal重新发起时替换原challenge, 不计入上限; 过期的不计入
+fn check_ic_pending(ic_pending : &BTreeMap<String, IcChallenge>, caller : &Principal, principal : &str,
+                    now : u64) -> Result<(), VerifyError> {
+    let active = || ic_pending.iter().filter(|(k, c)| c.deadline >= now && k.as_str() != principal);
+    if active().count() >= MAX_IC_PENDING
+        || active().filter(|(_, c)| c.requester.as_ref() == Some(caller)).count() >= MAX_IC_PENDING_PER_CALLER {
+        return Err(VerifyError::TooManyChallenges);
+    };
+    Ok(())
+}
+
+// xid owner取消待绑定ic身份
+#[update(name = "cancelIcVerify", guard="can_manage_identity")]
+#[candid_method(update, rename = "cancelIcVerify")]
+async fn cancel_ic_verify(arg : String) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.ic_pending.borrow_mut().remove(&arg) {
+            Some(_) => Ok(XidResponse::CancelOk),
+            None => Err(XidError::DataNotExist),
+        }
+    })
+}
+
+#[query(name = "getIcChallenges", guard="can_manage_identity")]
+#[candid_method(query, rename = "getIcChallenges")]
+fn get_ic_challenges() -> Vec<IcChallenge> {
     STATE.with(|s| {
-        *s.ic_verify.borrow_mut() = arg;
-        true
+        s.ic_pending.borrow().values().cloned().collect()
     })
 }
 
 // ic被绑定身份调用
 #[update(name = "verifyIcPost", guard="is_ic_authorized")]
 #[candid_method(update, rename = "verifyIcPost")]
-async fn verify_ic_post() -> Result<XidResponse, VerifyError> {
-    let ic_verify = STATE.with(|s| {
-        let mut ic_verify = s.ic_verify.borrow_mut();
-        let ic_tmp = ic_verify.clone();
-        *ic_verify = "".to_string();
-        ic_tmp
-    });
-    let id = ID {
-        platform: "ic".to_string(),
-        identity: ic_verify.clone(),
-        bind_time: ic_cdk::api::time().to_string(),
-    };
-    match Principal::from_text(&ic_verify.clone()) {
-        Err(_) => { return  Err(VerifyError::IcPrincipalErr); },
-        Ok(r) => {
-            if r == caller() {
-                let simple_id = SimpleId{
-                    platform: "ic".to_string(),
-                    identity: ic_verify.clone(),
-                };
-                let xid_center = Principal::from_text("sgdrt-caaaa-aaaal-qbola-cai").unwrap();
-                if let Ok((x, )) = ic::call::<_, (Result<(), XidCenterError>, ), _>(
-                    xid_center,
-                    "putID",
-                    (&simple_id, )
-                ).await {
-                    match x {
-                        Ok(_) => {},
-                        Err(er) => {
-                            return match er {
-                                XidCenterError::IDExist => {
-                                    Err(VerifyError::IDExist)
-                                },
-                                XidCenterError::XidNotExist => {
-                                    Err(VerifyError::XidNotExist)
-                                },
-                                _ => { Err(VerifyError::XidCNoNameErr) },
-                            };
-                        },
-                    }
-                };
-            } else {
-                return Err(VerifyError::VerifyErr);
-            };
-        },
-    };
-    STATE.with(|s | {
-        let mut ids = s.ids.borrow_mut();
-        let mut main_id = s.main_id.borrow_mut();
-        if *main_id.identity == "".to_string() {
-            *main_id = id.clone();
-        };
-        ids.insert(id);
-        Ok(XidResponse::VerifyOk)
-    })
+async fn verify_ic_post(nonce : String) -> Result<XidResponse, VerifyError> {
+    let principal = caller().to_text();
+    take_ic_challenge(&principal, |n| *n == nonce)?;
+    let proof = ic_proof(&principal, ProofMethod::IcCaller, ic_challenge_message(&nonce), vec![], None);
+    bind_ic(principal, proof).await
 }
 
-#[update(name = "verifyID", guard="is_authorized")]
-#[candid_method(update, rename = "verifyID")]
-async fn verify_id(msg : MsgIn) -> Result<XidResponse, VerifyError> {
+// xid owner提交待绑定身份的II委托链及其对challenge消息的签名
+#[update(name = "verifyIcDelegation", guard="can_manage_identity")]
+#[candid_method(update, rename = "verifyIcDelegation")]
+async fn verify_ic_delegation(arg : DelegationIn) -> Result<XidResponse, VerifyError> {
     let verify = Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap();
-    let mut pay_load = Payload::default();
-    if let Ok((x, )) = ic::call::<_, (Result<Payload, VerifyError>, ), _>(
+    let principal = match ic::call::<_, (Result<Principal, VerifyError>, ), _>(
         verify,
-        "msg_in",
-        (&msg, )
+        "delegation_in",
+        (&arg, )
     ).await {
-        match x {
-            Ok(p) => {
+        Ok((Ok(p), )) => p.to_text(),
+        Ok((Err(er), )) => return Err(er),
+        Err(_) => return Err(VerifyError::VerifyErr),
+    };
+    take_ic_challenge(&principal, |n| arg.message == ic_challenge_message(n))?;
+    let proof = ic_proof(&principal, ProofMethod::IcDelegation, arg.message.clone(), arg.signature.clone(), Some(arg));
+    bind_ic(principal, proof).await
+}
+
+fn ic_proof(principal : &str, method : ProofMethod, message : Vec<u8>, signature : Vec<u8>,
+            delegation : Option<DelegationIn>) -> BindingProof {
+    BindingProof {
+        id: SimpleId {
+            platform: "ic".to_string(),
+            identity: principal.to_string(),
+        },
+        method,
+        message: ByteBuf::from(message),
+        signature: ByteBuf::from(signature),
+        attestor_key_id: None,
+        delegation,
+        verified_at: ic_cdk::api::time(),
+    }
+}
+
+// 委托身份需签名的challenge消息
+fn ic_challenge_message(nonce : &str) -> Vec<u8> {
+    format!("xid-ic-verify:{}:{}", ic_cdk::id().to_text(), nonce).into_bytes()
+}
+
+// 校验并移除待绑定principal的challenge
+fn take_ic_challenge(principal : &str, check_nonce : impl Fn(&str) -> bool) -> Result<(), VerifyError> {
+    STATE.with(|s| {
+        let mut ic_pending = s.ic_pending.borrow_mut();
+        let challenge = match ic_pending.get(principal) {
+            Some(c) => c.clone(),
+            None => return Err(VerifyError::VerifyErr),
+        };
+        if challenge.deadline < ic_cdk::api::time() {
+            ic_pending.remove(principal);
+            return Err(VerifyError::ChallengeExpired);
+        };
+        if !check_nonce(&challenge.nonce) {
+            return Err(VerifyError::NonceErr);
+        };
+        ic_pending.remove(principal);
+        Ok(())
+    })
+}
+
+async fn bind_ic(ic_verify : String, proof : BindingProof) -> Result<XidResponse, VerifyError> {
+    let id = ID {
+        platform: "ic".to_string(),
+        identity: ic_verify.clone(),
+        bind_time: ic_cdk::api::time(),
+    };
+    if Principal::from_text(&ic_verify).is_err() {
+        return Err(VerifyError::IcPrincipalErr);
+    };
+    if STATE.with(|s| is_bound(&s.ids.borrow(), &proof.id)) {
+        return refresh_binding(proof).await;
+    };
+    let simple_id = SimpleId{
+        platform: "ic".to_string(),
+        identity: ic_verify.clone(),
+    };
+    let xid_center = Principal::from_text("sgdrt-caaaa-aaaal-qbola-cai").unwrap();
+    if let Ok((x, )) = ic::call::<_, (Result<(), XidCenterError>, ), _>(
+        xid_center,
+        "putID",
+        (&simple_id, )
+    ).await {
+        match x {
+            Ok(_) => {},
+            Err(er) => {
+                return match er {
+                    XidCenterError::IDExist => {
+                        Err(VerifyError::IDExist)
+                    },
+                    XidCenterError::XidNotExist => {
+                        Err(VerifyError::XidNotExist)
+                    },
+                    _ => { Err(VerifyError::XidCNoNameErr) },
+                };
+            },
+        }
+    };
+    STATE.with(|s | {
+        assign_first_main_id(s, &id);
+        s.ids.borrow_mut().insert(id);
+        s.revoked.borrow_mut().remove(&proof.id);
+        s.proofs.borrow_mut().insert(proof.id.clone(), proof);
+        // 状态在await之后才变更, 需在变更后再标记页面重新渲染
+        pages::invalidate();
+        Ok(XidResponse::VerifyOk)
+    })
+}
+
+// 重新验证已绑定的身份: 刷新bind_time与证明, 已通知过期的身份通知xid center恢复
+async fn refresh_binding(proof : BindingProof) -> Result<XidResponse, VerifyError> {
+    let was_stale = STATE.with(|s| {
+        let id = ID {
+            platform: proof.id.platform.clone(),
+            identity: proof.id.identity.clone(),
+            bind_time: proof.verified_at,
+        };
+        let mut ids = s.ids.borrow_mut();
+        if !ids.contains(&id) {
+            return Err(VerifyError::IDNotExist);
+        };
+        let mut main_id = s.main_id.borrow_mut();
+        if *main_id == id {
+            *main_id = id.clone();
+        };
+        ids.replace(id);
+        s.proofs.borrow_mut().insert(proof.id.clone(), proof.clone());
+        pages::invalidate();
+        Ok(s.stale_notified.borrow_mut().remove(&proof.id))
+    })?;
+    if was_stale {
+        notify_stale(proof.id, false).await;
+    };
+    Ok(XidResponse::VerifyOk)
+}
+
+// 通知xid center身份的过期状态, 失败时恢复标记, 由心跳重试
+async fn notify_stale(id : SimpleId, stale : bool) {
+    let xid_center = Principal::from_text("sgdrt-caaaa-aaaal-qbola-cai").unwrap();
+    let res = ic::call::<_, (Result<(), XidCenterError>, ), _>(
+        xid_center,
+        "setIDStale",
+        (&id, stale)
+    ).await;
+    if !matches!(res, Ok((Ok(_), ))) {
+        STATE.with(|s| {
+            let mut notified = s.stale_notified.borrow_mut();
+            if stale {
+                notified.remove(&id);
+            } else {
+                notified.insert(id);
+            };
+        });
+    };
+}
+
+#[update(name = "verifyID", guard="can_manage_identity")]
+#[candid_method(update, rename = "verifyID")]
+async fn verify_id(msg : MsgIn) -> Result<XidResponse, VerifyError> {
+    let verify = Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap();
+    let mut pay_load = Payload::default();
+    if let Ok((x, )) = ic::call::<_, (Result<Payload, VerifyError>, ), _>(
+        verify,
+        "msg_in",
+        (&msg, )
+    ).await {
+        match x {
+            Ok(p) => {
                 pay_load = p;
             },
             Err(er) => {return Err(er)},
@@ -375,7 +1093,11 @@ async fn verify_id(msg : MsgIn) -> Result<XidResponse, VerifyError> {
     let id = ID {
         platform: pay_load.platform.clone(),
         identity: pay_load.identity.clone(),
-        bind_time: ic_cdk::api::time().to_string(),
+        bind_time: ic_cdk::api::time(),
+    };
+    let proof = msg_proof(&pay_load, msg, id.bind_time);
+    if STATE.with(|s| is_bound(&s.ids.borrow(), &proof.id)) {
+        return refresh_binding(proof).await;
     };
     let simple_id = SimpleId{
         platform: pay_load.platform,
@@ -403,230 +1125,1987 @@ async fn verify_id(msg : MsgIn) -> Result<XidResponse, VerifyError> {
         }
     };
     STATE.with(|s| {
-        let mut ids = s.ids.borrow_mut();
-        let mut main_id = s.main_id.borrow_mut();
-        if *main_id.identity == "".to_string() {
-            *main_id = id.clone();
-        };
-        ids.insert(id);
+        assign_first_main_id(s, &id);
+        s.ids.borrow_mut().insert(id);
+        s.revoked.borrow_mut().remove(&proof.id);
+        s.proofs.borrow_mut().insert(proof.id.clone(), proof);
+        pages::invalidate();
     });
     Ok(XidResponse::VerifyOk)
 }
 
-#[update(name = "setXid", guard="is_authorized")]
+// 以新的签名消息重新验证已绑定身份; ic身份通过verifyIcPre重新验证
+#[update(name = "reverifyId", guard="can_manage_identity")]
+#[candid_method(update, rename = "reverifyId")]
+async fn reverify_id(msg : MsgIn) -> Result<XidResponse, VerifyError> {
+    let verify = Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap();
+    let pay_load = match ic::call::<_, (Result<Payload, VerifyError>, ), _>(
+        verify,
+        "msg_in",
+        (&msg, )
+    ).await {
+        Ok((Ok(p), )) => p,
+        Ok((Err(er), )) => return Err(er),
+        Err(_) => return Err(VerifyError::VerifyErr),
+    };
+    let proof = msg_proof(&pay_load, msg, ic_cdk::api::time());
+    refresh_binding(proof).await
+}
+
+fn msg_proof(pay_load : &Payload, msg : MsgIn, verified_at : u64) -> BindingProof {
+    BindingProof {
+        id: SimpleId {
+            platform: pay_load.platform.clone(),
+            identity: pay_load.identity.clone(),
+        },
+        method: ProofMethod::Secp256k1Keccak256,
+        message: ByteBuf::from(msg.msg.into_bytes()),
+        signature: ByteBuf::from(base64::decode(&msg.sig).unwrap_or_default()),
+        attestor_key_id: Some(verify::ATTESTOR_KEY_ID.to_string()),
+        delegation: None,
+        verified_at,
+    }
+}
+
+#[update(name = "setXid", guard="can_write_profile")]
 #[candid_method(update, rename = "setXid")]
 async fn set_xid(args : XidArgs) -> bool {
+    pages::invalidate();
     STATE.with(|s| {
-        match args.name {
-            Some(n) => {
-                *s.name.borrow_mut() = n;
-            },
-            None => {},
+        if let Some(n) = args.name {
+            *s.name.borrow_mut() = n;
         };
-        match args.avatar_url {
-            Some(url) => {
-                *s.avatar_url.borrow_mut() = url;
-            },
-            None => {},
+        if let Some(url) = args.avatar_url {
+            *s.avatar_url.borrow_mut() = url;
         }
     });
     true
 }
 
-#[update(name = "uploadAvatar", guard="is_authorized")]
+#[query(name = "http_request_streaming_callback")]
+#[candid_method(query, rename = "http_request_streaming_callback")]
+fn http_request_streaming_callback(token : StreamingCallbackToken) -> StreamingCallbackHttpResponse {
+    STATE.with(|s| {
+        let media = s.media.borrow();
+        match (media.get(&token.key), token_index(&token)) {
+            (Some(asset), Some(index)) if index < asset.chunks.len()
+                && token.sha256.as_ref().is_none_or(|h| *h == asset.sha256) => build_chunk(asset, index),
+            _ => build_chunk_end(),
+        }
+    })
+}
+
+// 头像指向本地媒体
+#[update(name = "setAvatarMedia", guard="can_write_profile")]
+#[candid_method(update, rename = "setAvatarMedia")]
+async fn set_avatar_media(key : String) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    STATE.with(|s| {
+        if !s.media.borrow().contains_key(&key) {
+            return Err(XidError::MediaNotExist);
+        };
+        *s.avatar_url.borrow_mut() = media::media_path(&key);
+        Ok(XidResponse::UploadOk)
+    })
+}
+
+// 分块上传: createUpload -> uploadChunk * n -> commitUpload
+#[update(name = "createUpload", guard="can_write_content")]
+#[candid_method(update, rename = "createUpload")]
+async fn create_upload(arg : CreateUploadArgs) -> Result<u64, XidError> {
+    media::check_key(&arg.key)?;
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        let mut sessions = s.upload_sessions.borrow_mut();
+        if sessions.len() >= media::MAX_UPLOAD_SESSIONS {
+            return Err(XidError::FieldOutOfRange);
+        };
+        media::check_count(&s.media.borrow(), &arg.key)?;
+        let mut next_id = s.next_upload_id.borrow_mut();
+        *next_id += 1;
+        sessions.insert(*next_id, UploadSession {
+            key: arg.key,
+            content_type: arg.content_type,
+            chunks: BTreeMap::new(),
+            size: 0,
+            expire_time: now.saturating_add(media::UPLOAD_SESSION_TTL),
+        });
+        Ok(*next_id)
+    })
+}
+
+// 同一index重复上传时覆盖
+#[update(name = "uploadChunk", guard="can_write_content")]
+#[candid_method(update, rename = "uploadChunk")]
+async fn upload_chunk(upload_id : u64, index : u64, content : ByteBuf) -> Result<XidResponse, XidError> {
+    if content.is_empty() || content.len() > media::MAX_CHUNK_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    STATE.with(|s| {
+        let mut sessions = s.upload_sessions.borrow_mut();
+        let total = media::total_size(&s.media.borrow(), &sessions);
+        let session = match sessions.get_mut(&upload_id) {
+            Some(session) => session,
+            None => return Err(XidError::UploadNotExist),
+        };
+        let replaced = session.chunks.get(&index).map_or(0, |c| c.len() as u64);
+        let size = session.size - replaced + content.len() as u64;
+        if size > media::MAX_MEDIA_SIZE {
+            return Err(XidError::FieldOutOfRange);
+        };
+        media::check_total(total.saturating_sub(replaced), content.len() as u64)?;
+        session.chunks.insert(index, content);
+        session.size = size;
+        Ok(XidResponse::UploadOk)
+    })
+}
+
+// 校验通过后写入媒体库, 同名key被覆盖; 校验失败时会话保留以便重传
+#[update(name = "commitUpload", guard="can_write_content")]
+#[candid_method(update, rename = "commitUpload")]
+async fn commit_upload(arg : CommitUploadArgs) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        let mut sessions = s.upload_sessions.borrow_mut();
+        let sha256 = match sessions.get(&arg.upload_id) {
+            Some(session) => {
+                media::check_count(&s.media.borrow(), &session.key)?;
+                media::check(session, &arg.sha256)?
+            },
+            None => return Err(XidError::UploadNotExist),
+        };
+        if let Some(session) = sessions.remove(&arg.upload_id) {
+            let asset = media::into_asset(session, sha256, now);
+            certify::put(&media::media_path(&asset.key), media::certified_hash(&asset));
+            s.media.borrow_mut().insert(asset.key.clone(), asset);
+        };
+        Ok(XidResponse::UploadOk)
+    })
+}
+
+#[update(name = "cancelUpload", guard="can_write_content")]
+#[candid_method(update, rename = "cancelUpload")]
+async fn cancel_upload(upload_id : u64) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.upload_sessions.borrow_mut().remove(&upload_id) {
+            Some(_) => Ok(XidResponse::CancelOk),
+            None => Err(XidError::UploadNotExist),
+        }
+    })
+}
+
+#[update(name = "deleteMedia", guard="can_write_content")]
+#[candid_method(update, rename = "deleteMedia")]
+async fn delete_media(key : String) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    STATE.with(|s| {
+        match s.media.borrow_mut().remove(&key) {
+            Some(_) => {
+                certify::remove(&media::media_path(&key));
+                Ok(XidResponse::DeleteOk)
+            },
+            None => Err(XidError::MediaNotExist),
+        }
+    })
+}
+
+#[query(name = "getMediaList")]
+#[candid_method(query, rename = "getMediaList")]
+fn get_media_list() -> Vec<MediaInfo> {
+    STATE.with(|s| {
+        s.media.borrow().values().map(|asset| asset.info()).collect()
+    })
+}
+
+#[update(name = "uploadAvatar", guard="can_write_profile")]
 #[candid_method(update, rename = "uploadAvatar")]
 async fn upload_avatar(avatar : Avatar) -> bool {
+    pages::invalidate();
     STATE.with(|s| {
+        certify_avatar(&avatar);
         *s.avatar.borrow_mut() = avatar;
     });
     true
 }
 
-#[update(name = "uploadStore", guard="is_authorized")]
+#[update(name = "uploadStore", guard="can_write_content")]
 #[candid_method(update, rename = "uploadStore")]
-async fn upload_store(arg : StoreArg) -> Result<XidResponse, XidError> {
-     STATE.with(|s| {
-         let pub_key = s.pub_key.borrow();
-         return match arg.content {
-             Contents::TwitterContent(t) => {
-                 let mut twitter_store = s.twitter_store.borrow_mut();
-                 if twitter_store.contains_key(&arg.uuid) {
-                     Err(XidError::UuidRepeat)
-                 } else {
-                     twitter_store.insert(arg.uuid.clone(), TwitterStorage {
-                         owner: pub_key.clone(),
-                         uuid: arg.uuid,
-                         twitter_content: t,
-                         d_platform: arg.d_platform,
-                         is_minted: false,
-                         mint_time: "".to_string(),
-                         upload_time: ic_cdk::api::time().to_string(),
-                     });
-                     Ok(XidResponse::StoreOk)
-                 }
-             },
-             Contents::OffChainContent(o) => {
-                 let mut off_store = s.off_store.borrow_mut();
-                 if off_store.contains_key(&arg.uuid) {
-                     Err(XidError::UuidRepeat)
-                 } else {
-                     off_store.insert(arg.uuid.clone(), OffStorage {
-                         owner: pub_key.clone(),
-                         uuid: arg.uuid,
-                         off_content: o,
-                         d_platform: arg.d_platform,
-                         is_minted: false,
-                         mint_time: "".to_string(),
-                         upload_time: ic_cdk::api::time().to_string(),
-                     });
-                     Ok(XidResponse::StoreOk)
-                 }
-             }
-         };
-    })
-}
-
-#[update(name = "deleteStore", guard="is_authorized")]
-#[candid_method(update, rename = "deleteStore")]
-async fn delete_store(arg : ContentUuid) -> Result<XidResponse, XidError> {
-    STATE.with(|s|{
-        return match arg.content_type {
-            ContentType::OffChain => {
-                let mut off_store = s.off_store.borrow_mut();
-                match off_store.remove(&arg.uuid) {
-                    Some(_) => {
-                        Ok(XidResponse::DeleteOk)
-                    },
-                    None => {
-                        Err(XidError::UuidNotExist)
-                    },
-                }
-            },
-            ContentType::Twitter => {
-                let mut twitter_store = s.twitter_store.borrow_mut();
-                match twitter_store.remove(&arg.uuid) {
-                    Some(_) => {
-                        Ok(XidResponse::DeleteOk)
-                    },
-                    None => {
-                        Err(XidError::UuidNotExist)
-                    },
-                }
-            }
+async fn upload_store(mut arg : StoreArg) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let content_type = arg.content.content_type();
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        check_content(s, &mut arg.content, now)?;
+        let key = ContentUuid { content_type: content_type.clone(), uuid: arg.uuid.clone() };
+        if s.trash.borrow().contains_key(&key) {
+            return Err(XidError::UuidRepeat);
         };
+        let mut stores = s.stores.borrow_mut();
+        match stores.entry(content_type.clone()).or_default().entry(arg.uuid.clone()) {
+            Entry::Occupied(_) => Err(XidError::UuidRepeat),
+            Entry::Vacant(e) => {
+                let item = e.insert(Storage {
+                    owner: s.pub_key.borrow().clone(),
+                    uuid: arg.uuid,
+                    content_type,
+                    content: arg.content,
+                    d_platform: arg.d_platform,
+                    is_minted: false,
+                    mint_time: None,
+                    upload_time: now,
+                    edit_time: None,
+                });
+                s.timeline.borrow_mut().insert(item.timeline_key());
+                s.search_index.borrow_mut().insert(item);
+                Ok(XidResponse::StoreOk)
+            },
+        }
     })
 }
 
-#[update(name = "setMintStatus", guard="is_authorized")]
-#[candid_method(update, rename = "setMintStatus")]
-async fn set_mint_status(arg : ContentUuid) -> Result<XidResponse, XidError> {
+// 修改内容并保留原版本, content类型须与原内容一致
+#[update(name = "updateStore", guard="can_write_content")]
+#[candid_method(update, rename = "updateStore")]
+async fn update_store(mut arg : StoreArg) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let key = ContentUuid {
+        content_type: arg.content.content_type(),
+        uuid: arg.uuid,
+    };
+    let now = ic_cdk::api::time();
     STATE.with(|s| {
-        match arg.content_type {
-            ContentType::OffChain => {
-                let mut off_store = s.off_store.borrow_mut();
-                match off_store.get(&arg.uuid) {
-                    Some(os) => {
-                        let res = os.clone();
-                        off_store.insert( arg.uuid, OffStorage{
-                            owner: res.owner,
-                            uuid: res.uuid,
-                            off_content: res.off_content,
-                            d_platform: res.d_platform,
-                            is_minted: true,
-                            mint_time: ic_cdk::api::time().to_string(),
-                            upload_time: res.upload_time,
-                        })
-                    },
-                    None => { return Err(XidError::UuidNotExist) },
-                };
-            },
-            ContentType::Twitter => {
-                let mut twitter_store = s.twitter_store.borrow_mut();
-                match twitter_store.get(&arg.uuid) {
-                    Some(ts) => {
-                        let res = ts.clone();
-                        twitter_store.insert( arg.uuid, TwitterStorage{
-                            owner: res.owner,
-                            uuid: res.uuid,
-                            twitter_content: res.twitter_content,
-                            d_platform: res.d_platform,
-                            is_minted: true,
-                            mint_time: ic_cdk::api::time().to_string(),
-                            upload_time: res.upload_time,
-                        })
-                    },
-                    None => { return Err(XidError::UuidNotExist) }
-                };
-            }
-        };
-        return Ok(XidResponse::MintOk)
+        check_content(s, &mut arg.content, now)?;
+        edit_store(s, &key, arg.content, arg.d_platform, now)?;
+        Ok(XidResponse::UpdateOk)
     })
 }
 
-fn is_authorized() -> Result<(), String> {
+// 恢复到指定历史版本, 恢复本身也记为一次修改
+#[update(name = "revertStore", guard="can_write_content")]
+#[candid_method(update, rename = "revertStore")]
+async fn revert_store(arg : ContentUuid, version : u64) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let now = ic_cdk::api::time();
     STATE.with(|s| {
-        if *s.pub_key.borrow() == caller().to_text() {
-            Ok(())
-        } else {
-            Err("Caller is not authorized".to_string())
+        let target = s.versions.borrow().get(&arg)
+            .and_then(|history| history.iter().find(|v| v.version == version))
+            .cloned();
+        match target {
+            Some(v) => {
+                edit_store(s, &arg, v.content, v.d_platform, now)?;
+                Ok(XidResponse::RevertOk)
+            },
+            None => Err(XidError::VersionNotExist),
         }
     })
 }
 
-fn is_ic_authorized() -> Result<(), String> {
+#[query(name = "getStoreVersions")]
+#[candid_method(query, rename = "getStoreVersions")]
+fn get_store_versions(arg : ContentUuid) -> Vec<StoreVersion> {
     STATE.with(|s| {
-        if *s.ic_verify.borrow() == caller().to_text() {
+        s.versions.borrow().get(&arg).cloned().unwrap_or_default()
+    })
+}
+
+// 校验待写入的内容: 推文发布时间(换算为纳秒)与自定义类型的schema
+fn check_content(s : &State, content : &mut Contents, now : u64) -> Result<(), XidError> {
+    match content {
+        Contents::TwitterContent(t) => {
+            t.post_time = timestamp::normalize_post_time(t.post_time, now)?;
             Ok(())
-        } else {
-            Err("Caller is not authorized".to_string())
+        },
+        Contents::Custom(c) => match s.schemas.borrow().get(&c.content_type) {
+            Some(schema) => schema::validate(schema, c),
+            None => Err(XidError::ContentTypeNotExist),
+        },
+        Contents::OffChainContent(_) => Ok(()),
+    }
+}
+
+// 替换内容并把原版本写入历史, 已铸造的内容不可修改
+fn edit_store(s : &State, key : &ContentUuid, content : Contents, d_platform : String, now : u64) -> Result<(), XidError> {
+    let mut stores = s.stores.borrow_mut();
+    let item = match stores.get_mut(&key.content_type).and_then(|store| store.get_mut(&key.uuid)) {
+        Some(item) => item,
+        None => return Err(XidError::UuidNotExist),
+    };
+    if item.is_minted {
+        return Err(XidError::ItemMinted);
+    };
+    let changes = history::diff(&item.content, &item.d_platform, &content, &d_platform);
+    if changes.is_empty() {
+        return Ok(());
+    };
+    s.timeline.borrow_mut().remove(&item.timeline_key());
+    s.search_index.borrow_mut().remove(item);
+    let mut versions = s.versions.borrow_mut();
+    let history = versions.entry(key.clone()).or_default();
+    history.push(StoreVersion {
+        version: history.last().map_or(1, |v| v.version + 1),
+        content: std::mem::replace(&mut item.content, content),
+        d_platform: std::mem::replace(&mut item.d_platform, d_platform),
+        edit_time: now,
+        changes,
+    });
+    if history.len() > history::MAX_VERSIONS {
+        history.remove(0);
+    };
+    item.edit_time = Some(now);
+    s.timeline.borrow_mut().insert(item.timeline_key());
+    s.search_index.borrow_mut().insert(item);
+    Ok(())
+}
+
+// 移入回收站, 保留期内可恢复
+#[update(name = "deleteStore", guard="can_write_content")]
+#[candid_method(update, rename = "deleteStore")]
+async fn delete_store(arg : ContentUuid) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    let now = ic_cdk::api::time();
+    STATE.with(|s| trash_item(s, arg, now))
+}
+
+fn trash_item(s : &State, arg : ContentUuid, now : u64) -> Result<XidResponse, XidError> {
+    let removed = s.stores.borrow_mut()
+        .get_mut(&arg.content_type)
+        .and_then(|store| store.remove(&arg.uuid));
+    let item = match removed {
+        Some(item) => item,
+        None => return Err(XidError::UuidNotExist),
+    };
+    s.timeline.borrow_mut().remove(&item.timeline_key());
+    s.search_index.borrow_mut().remove(&item);
+    // 从标签与合集中摘除, 记录位置以便恢复
+    let mut tags = Vec::new();
+    s.tags.borrow_mut().retain(|tag, items| {
+        if items.remove(&arg) {
+            tags.push(tag.clone());
+        };
+        !items.is_empty()
+    });
+    let mut collections = Vec::new();
+    for c in s.collections.borrow_mut().values_mut() {
+        if let Some(i) = c.items.iter().position(|k| *k == arg) {
+            c.items.remove(i);
+            collections.push((c.name.clone(), i as u64));
+        };
+    }
+    s.trash.borrow_mut().insert(arg, TrashEntry {
+        item,
+        delete_time: now,
+        expire_time: now.saturating_add(TRASH_RETENTION),
+        tags,
+        collections,
+    });
+    Ok(XidResponse::DeleteOk)
+}
+
+#[update(name = "restoreStore", guard="can_write_content")]
+#[candid_method(update, rename = "restoreStore")]
+async fn restore_store(arg : ContentUuid) -> Result<XidResponse, XidError> {
+    pages::invalidate();
+    STATE.with(|s| restore_item(s, arg))
+}
+
+fn restore_item(s : &State, arg : ContentUuid) -> Result<XidResponse, XidError> {
+    let entry = match s.trash.borrow_mut().remove(&arg) {
+        Some(entry) => entry,
+        None => return Err(XidError::UuidNotExist),
+    };
+    s.timeline.borrow_mut().insert(entry.item.timeline_key());
+    s.search_index.borrow_mut().insert(&entry.item);
+    // 标签已被移除且标签数已达上限时不再重建
+    let mut tags = s.tags.borrow_mut();
+    for tag in entry.tags {
+        if !tags.contains_key(&tag) && tags.len() >= MAX_TAGS {
+            continue;
+        };
+        tags.entry(tag).or_default().insert(arg.clone());
+    }
+    // 合集已被删除或已满的忽略
+    let mut collections = s.collections.borrow_mut();
+    for (name, i) in entry.collections {
+        if let Some(c) = collections.get_mut(&name).filter(|c| c.items.len() < MAX_COLLECTION_ITEMS) {
+            let i = (i as usize).min(c.items.len());
+            c.items.insert(i, arg.clone());
+        };
+    }
+    s.stores.borrow_mut()
+        .entry(arg.content_type)
+        .or_default()
+        .insert(arg.uuid, entry.item);
+    Ok(XidResponse::RestoreOk)
+}
+
+// 立即彻底删除回收站中的内容
+#[update(name = "purgeStore", guard="can_write_content")]
+#[candid_method(update, rename = "purgeStore")]
+async fn purge_store(arg : ContentUuid) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.trash.borrow_mut().remove(&arg) {
+            Some(_) => {
+                s.versions.borrow_mut().remove(&arg);
+                Ok(XidResponse::PurgeOk)
+            },
+            None => Err(XidError::UuidNotExist),
         }
     })
 }
 
-fn do_clear() {
+#[query(name = "getTrash", guard="can_write_content")]
+#[candid_method(query, rename = "getTrash")]
+fn get_trash(cursor : Option<String>, limit : u64) -> Result<TrashPage, XidError> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let lower = match &cursor {
+        Some(c) => match decode_content_cursor(c) {
+            Some(key) => Bound::Excluded(key),
+            None => return Err(XidError::FieldOutOfRange),
+        },
+        None => Bound::Unbounded,
+    };
     STATE.with(|s| {
-        s.pub_key.borrow_mut().clear();
-        s.name.borrow_mut().clear();
-        s.ids.borrow_mut().clear();
-        s.ic_verify.borrow_mut().clear();
-        s.avatar_url.borrow_mut().clear();
-        s.twitter_store.borrow_mut().clear();
-        s.off_store.borrow_mut().clear();
+        let trash = s.trash.borrow();
+        let entries : Vec<(&ContentUuid, &TrashEntry)> = trash
+            .range((lower, Bound::Unbounded))
+            .take(limit as usize)
+            .collect();
+        let next_cursor = if entries.len() as u64 == limit {
+            entries.last().map(|(key, _)| encode_content_cursor(key))
+        } else {
+            None
+        };
+        Ok(TrashPage {
+            items: entries.into_iter().map(|(_, entry)| entry.clone()).collect(),
+            next_cursor,
+        })
     })
 }
 
-#[pre_upgrade]
-fn pre_upgrade() {
-    let stable_state : StableState = STATE.with(|s| StableState{
-        pub_key: s.pub_key.take(),
-        name: s.name.take(),
-        main_id: s.main_id.take(),
-        ids: s.ids.take(),
-        ic_verify: s.ic_verify.take(),
-        avatar_url: s.avatar_url.take(),
-        avatar: s.avatar.take(),
-        twitter_store: s.twitter_store.take(),
-        off_store: s.off_store.take(),
+// 全局定时器: 数据变化RENDER_DELAY后重新渲染公开页面;
+// 每PURGE_INTERVAL清理过期的回收站内容, 上传会话与ic验证challenge, 回收站每次最多清理PURGE_BATCH条;
+// 同时将身份过期状态的变化通知xid center, 每次最多STALE_NOTIFY_BATCH条
+#[export_name = "canister_global_timer"]
+fn global_timer() {
+    ic_cdk::setup();
+    timer::fired();
+    if pages::take_dirty() {
+        refresh_pages();
+    };
+    let now = ic_cdk::api::time();
+    let (changes, next_purge) = STATE.with(|s| {
+        let mut last_purge = s.last_purge.borrow_mut();
+        if now < last_purge.saturating_add(PURGE_INTERVAL) {
+            return (vec![], last_purge.saturating_add(PURGE_INTERVAL));
+        };
+        *last_purge = now;
+        let mut trash = s.trash.borrow_mut();
+        let expired : Vec<ContentUuid> = trash.iter()
+            .filter(|(_, entry)| entry.expire_time <= now)
+            .map(|(key, _)| key.clone())
+            .take(PURGE_BATCH)
+            .collect();
+        let mut versions = s.versions.borrow_mut();
+        for key in expired {
+            trash.remove(&key);
+            versions.remove(&key);
+        }
+        s.upload_sessions.borrow_mut().retain(|_, session| session.expire_time > now);
+        s.ic_pending.borrow_mut().retain(|_, c| c.deadline >= now);
+        (stale_changes(s, now), now.saturating_add(PURGE_INTERVAL))
     });
-    ic::stable_store((stable_state, )).expect("failed to save stable state");
+    timer::schedule(next_purge);
+    for (id, stale) in changes {
+        ic_cdk::spawn(notify_stale(id, stale));
+    }
 }
 
-#[post_upgrade]
-fn post_upgrade() {
-    do_clear();
-    let (stable_state,) : (StableState, ) =
-        ic::stable_restore().expect("failed to restore stable state");
-
-    STATE.with(|s| {
-        s.pub_key.replace(stable_state.pub_key);
-        s.name.replace(stable_state.name);
-        s.main_id.replace(stable_state.main_id);
-        s.ids.replace(stable_state.ids);
-        s.ic_verify.replace(stable_state.ic_verify);
-        s.avatar_url.replace(stable_state.avatar_url);
-        s.avatar.replace(stable_state.avatar);
-        s.twitter_store.replace(stable_state.twitter_store);
-        s.off_store.replace(stable_state.off_store);
-    })
+// 过期状态与已通知状态不一致的身份, 先行更新标记
+fn stale_changes(s : &State, now : u64) -> Vec<(SimpleId, bool)> {
+    let ids = s.ids.borrow();
+    let mut notified = s.stale_notified.borrow_mut();
+    let mut changes = Vec::new();
+    for id in ids.iter() {
+        if changes.len() >= STALE_NOTIFY_BATCH {
+            break;
+        };
+        let simple_id = SimpleId {
+            platform: id.platform.clone(),
+            identity: id.identity.clone(),
+        };
+        let stale = is_stale(s, id, now);
+        if stale != notified.contains(&simple_id) {
+            if stale {
+                notified.insert(simple_id.clone());
+            } else {
+                notified.remove(&simple_id);
+            };
+            changes.push((simple_id, stale));
+        };
+    }
+    changes
 }
 
+#[update(name = "setMintStatus", guard="can_write_content")]
+#[candid_method(update, rename = "setMintStatus")]
+async fn set_mint_status(arg : ContentUuid) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        let mut stores = s.stores.borrow_mut();
+        match stores.get_mut(&arg.content_type).and_then(|store| store.get_mut(&arg.uuid)) {
+            Some(item) => {
+                item.is_minted = true;
+                item.mint_time = Some(ic_cdk::api::time());
+                Ok(XidResponse::MintOk)
+            },
+            None => Err(XidError::UuidNotExist),
+        }
+    })
+}
+
+// xid owner注册或更新自定义内容类型
+#[update(name = "registerContentType", guard="is_authorized")]
+#[candid_method(update, rename = "registerContentType")]
+async fn register_content_type(arg : ContentSchema) -> Result<XidResponse, XidError> {
+    schema::check_schema(&arg)?;
+    STATE.with(|s| {
+        s.schemas.borrow_mut().insert(arg.name.clone(), arg);
+        Ok(XidResponse::RegisterOk)
+    })
+}
+
+// 仅能删除没有存储内容的类型, 回收站中的内容可被恢复, 同样视为在用
+#[update(name = "removeContentType", guard="is_authorized")]
+#[candid_method(update, rename = "removeContentType")]
+async fn remove_content_type(name : String) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        let content_type = ContentType::Custom(name.clone());
+        if s.stores.borrow().get(&content_type).is_some_and(|store| !store.is_empty())
+            || s.trash.borrow().keys().any(|key| key.content_type == content_type) {
+            return Err(XidError::ContentTypeInUse);
+        };
+        match s.schemas.borrow_mut().remove(&name) {
+            Some(_) => {
+                s.stores.borrow_mut().remove(&content_type);
+                Ok(XidResponse::DeleteOk)
+            },
+            None => Err(XidError::ContentTypeNotExist),
+        }
+    })
+}
+
+// 标签统一为小写并去除首尾空白
+fn normalize_tag(tag : &str) -> Result<String, XidError> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN || tag.chars().any(|c| c.is_control()) {
+        return Err(XidError::InvalidName);
+    };
+    Ok(tag)
+}
+
+fn check_collection_name(name : &str) -> Result<(), XidError> {
+    if name.trim().is_empty() || name.chars().count() > schema::MAX_NAME_LEN || name.chars().any(|c| c.is_control()) {
+        return Err(XidError::InvalidName);
+    };
+    Ok(())
+}
+
+fn check_items_exist(stores : &BTreeMap<ContentType, BTreeMap<String, Storage>>, items : &[ContentUuid]) -> Result<(), XidError> {
+    if items.is_empty() {
+        return Err(XidError::FieldOutOfRange);
+    };
+    if !items.iter().all(|k| stores.get(&k.content_type).is_some_and(|store| store.contains_key(&k.uuid))) {
+        return Err(XidError::UuidNotExist);
+    };
+    Ok(())
+}
+
+// owner与ContentWrite delegate可见非公开合集
+fn can_view_private() -> bool {
+    can_write_content().is_ok()
+}
+
+#[update(name = "tagItems", guard="can_write_content")]
+#[candid_method(update, rename = "tagItems")]
+async fn tag_items(tag : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    let tag = normalize_tag(&tag)?;
+    STATE.with(|s| add_tag(s, tag, items))
+}
+
+fn add_tag(s : &State, tag : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    check_items_exist(&s.stores.borrow(), &items)?;
+    let mut tags = s.tags.borrow_mut();
+    if !tags.contains_key(&tag) && tags.len() >= MAX_TAGS {
+        return Err(XidError::FieldOutOfRange);
+    };
+    tags.entry(tag).or_default().extend(items);
+    Ok(XidResponse::TagOk)
+}
+
+#[update(name = "untagItems", guard="can_write_content")]
+#[candid_method(update, rename = "untagItems")]
+async fn untag_items(tag : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    let tag = normalize_tag(&tag)?;
+    STATE.with(|s| remove_tag(s, &tag, &items))
+}
+
+// 标签下的内容全部移除后删除该标签
+fn remove_tag(s : &State, tag : &str, items : &[ContentUuid]) -> Result<XidResponse, XidError> {
+    let mut tags = s.tags.borrow_mut();
+    let tagged = match tags.get_mut(tag) {
+        Some(tagged) => tagged,
+        None => return Err(XidError::DataNotExist),
+    };
+    for key in items.iter() {
+        tagged.remove(key);
+    }
+    if tagged.is_empty() {
+        tags.remove(tag);
+    };
+    Ok(XidResponse::TagOk)
+}
+
+// 所有标签及其内容数
+#[query(name = "getTags")]
+#[candid_method(query, rename = "getTags")]
+fn get_tags() -> Vec<(String, u64)> {
+    STATE.with(|s| {
+        s.tags.borrow().iter().map(|(tag, items)| (tag.clone(), items.len() as u64)).collect()
+    })
+}
+
+#[query(name = "getItemTags")]
+#[candid_method(query, rename = "getItemTags")]
+fn get_item_tags(arg : ContentUuid) -> Vec<String> {
+    STATE.with(|s| {
+        s.tags.borrow().iter()
+            .filter(|(_, items)| items.contains(&arg))
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    })
+}
+
+#[query(name = "listByTag")]
+#[candid_method(query, rename = "listByTag")]
+fn list_by_tag(tag : String, cursor : Option<String>, limit : u64) -> Result<StorePage, XidError> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let tag = normalize_tag(&tag)?;
+    let lower = match &cursor {
+        Some(c) => match decode_content_cursor(c) {
+            Some(key) => Bound::Excluded(key),
+            None => return Err(XidError::FieldOutOfRange),
+        },
+        None => Bound::Unbounded,
+    };
+    STATE.with(|s| {
+        let tags = s.tags.borrow();
+        let keys : Vec<&ContentUuid> = match tags.get(&tag) {
+            Some(items) => items.range((lower, Bound::Unbounded)).take(limit as usize).collect(),
+            None => return Err(XidError::DataNotExist),
+        };
+        Ok(build_page(&s.stores.borrow(), &keys, limit))
+    })
+}
+
+#[update(name = "createCollection", guard="can_write_content")]
+#[candid_method(update, rename = "createCollection")]
+async fn create_collection(arg : CollectionArgs) -> Result<XidResponse, XidError> {
+    check_collection_name(&arg.name)?;
+    if arg.description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        let mut collections = s.collections.borrow_mut();
+        if collections.len() >= MAX_COLLECTIONS {
+            return Err(XidError::FieldOutOfRange);
+        };
+        match collections.entry(arg.name.clone()) {
+            Entry::Occupied(_) => Err(XidError::CollectionExist),
+            Entry::Vacant(e) => {
+                e.insert(Collection {
+                    name: arg.name,
+                    description: arg.description,
+                    is_public: arg.is_public,
+                    items: Vec::new(),
+                    create_time: now,
+                    update_time: now,
+                });
+                Ok(XidResponse::CollectionOk)
+            },
+        }
+    })
+}
+
+#[update(name = "updateCollection", guard="can_write_content")]
+#[candid_method(update, rename = "updateCollection")]
+async fn update_collection(arg : CollectionArgs) -> Result<XidResponse, XidError> {
+    if arg.description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(XidError::FieldOutOfRange);
+    };
+    STATE.with(|s| {
+        match s.collections.borrow_mut().get_mut(&arg.name) {
+            Some(c) => {
+                c.description = arg.description;
+                c.is_public = arg.is_public;
+                c.update_time = ic_cdk::api::time();
+                Ok(XidResponse::CollectionOk)
+            },
+            None => Err(XidError::CollectionNotExist),
+        }
+    })
+}
+
+#[update(name = "deleteCollection", guard="can_write_content")]
+#[candid_method(update, rename = "deleteCollection")]
+async fn delete_collection(name : String) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.collections.borrow_mut().remove(&name) {
+            Some(_) => Ok(XidResponse::DeleteOk),
+            None => Err(XidError::CollectionNotExist),
+        }
+    })
+}
+
+// 追加到合集末尾, 已在合集中的内容忽略
+#[update(name = "addToCollection", guard="can_write_content")]
+#[candid_method(update, rename = "addToCollection")]
+async fn add_to_collection(name : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    let now = ic_cdk::api::time();
+    STATE.with(|s| add_collection_items(s, &name, items, now))
+}
+
+fn add_collection_items(s : &State, name : &str, items : Vec<ContentUuid>, now : u64) -> Result<XidResponse, XidError> {
+    check_items_exist(&s.stores.borrow(), &items)?;
+    let mut collections = s.collections.borrow_mut();
+    let c = match collections.get_mut(name) {
+        Some(c) => c,
+        None => return Err(XidError::CollectionNotExist),
+    };
+    let mut present : BTreeSet<ContentUuid> = c.items.iter().cloned().collect();
+    let added : Vec<ContentUuid> = items.into_iter().filter(|k| present.insert(k.clone())).collect();
+    if c.items.len() + added.len() > MAX_COLLECTION_ITEMS {
+        return Err(XidError::FieldOutOfRange);
+    };
+    c.items.extend(added);
+    c.update_time = now;
+    Ok(XidResponse::CollectionOk)
+}
+
+#[update(name = "removeFromCollection", guard="can_write_content")]
+#[candid_method(update, rename = "removeFromCollection")]
+async fn remove_from_collection(name : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    let now = ic_cdk::api::time();
+    STATE.with(|s| remove_collection_items(s, &name, &items, now))
+}
+
+fn remove_collection_items(s : &State, name : &str, items : &[ContentUuid], now : u64) -> Result<XidResponse, XidError> {
+    match s.collections.borrow_mut().get_mut(name) {
+        Some(c) => {
+            c.items.retain(|k| !items.contains(k));
+            c.update_time = now;
+            Ok(XidResponse::CollectionOk)
+        },
+        None => Err(XidError::CollectionNotExist),
+    }
+}
+
+// 调整顺序, items须恰为合集现有内容的一个排列
+#[update(name = "reorderCollection", guard="can_write_content")]
+#[candid_method(update, rename = "reorderCollection")]
+async fn reorder_collection(name : String, items : Vec<ContentUuid>) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        match s.collections.borrow_mut().get_mut(&name) {
+            Some(c) => {
+                let current : BTreeSet<&ContentUuid> = c.items.iter().collect();
+                let given : BTreeSet<&ContentUuid> = items.iter().collect();
+                if given.len() != items.len() || current != given {
+                    return Err(XidError::FieldOutOfRange);
+                };
+                c.items = items;
+                c.update_time = ic_cdk::api::time();
+                Ok(XidResponse::CollectionOk)
+            },
+            None => Err(XidError::CollectionNotExist),
+        }
+    })
+}
+
+#[query(name = "getCollections")]
+#[candid_method(query, rename = "getCollections")]
+fn get_collections() -> Vec<CollectionInfo> {
+    let show_private = can_view_private();
+    STATE.with(|s| {
+        s.collections.borrow().values()
+            .filter(|c| c.is_public || show_private)
+            .map(|c| c.info())
+            .collect()
+    })
+}
+
+// 按合集顺序分页, cursor为上一页最后一项
+#[query(name = "listCollection")]
+#[candid_method(query, rename = "listCollection")]
+fn list_collection(name : String, cursor : Option<String>, limit : u64) -> Result<StorePage, XidError> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let show_private = can_view_private();
+    STATE.with(|s| {
+        let collections = s.collections.borrow();
+        let c = match collections.get(&name) {
+            Some(c) if c.is_public || show_private => c,
+            _ => return Err(XidError::CollectionNotExist),
+        };
+        let start = match &cursor {
+            Some(cur) => match decode_content_cursor(cur).and_then(|key| c.items.iter().position(|k| *k == key)) {
+                Some(i) => i + 1,
+                None => return Err(XidError::FieldOutOfRange),
+            },
+            None => 0,
+        };
+        let keys : Vec<&ContentUuid> = c.items.iter().skip(start).take(limit as usize).collect();
+        Ok(build_page(&s.stores.borrow(), &keys, limit))
+    })
+}
+
+fn build_page(stores : &BTreeMap<ContentType, BTreeMap<String, Storage>>, keys : &[&ContentUuid], limit : u64) -> StorePage {
+    let items : Vec<Storage> = keys.iter().filter_map(|key| {
+        stores.get(&key.content_type).and_then(|store| store.get(&key.uuid)).cloned()
+    }).collect();
+    let next_cursor = if keys.len() as u64 == limit {
+        keys.last().map(|key| encode_content_cursor(key))
+    } else {
+        None
+    };
+    StorePage { items, next_cursor }
+}
+
+#[update(name = "addDelegate", guard="is_authorized")]
+#[candid_method(update, rename = "addDelegate")]
+async fn add_delegate(arg : DelegateArgs) -> Result<XidResponse, XidError> {
+    let now = ic_cdk::api::time();
+    if arg.scopes.is_empty() || arg.expiry.is_some_and(|e| e <= now) {
+        return Err(XidError::FieldOutOfRange);
+    };
+    STATE.with(|s| {
+        let principal = arg.principal.to_text();
+        if *s.pub_key.borrow() == principal {
+            return Err(XidError::FieldOutOfRange);
+        };
+        s.delegates.borrow_mut().insert(principal.clone(), Delegate {
+            principal,
+            scopes: arg.scopes,
+            expiry: arg.expiry,
+            add_time: now,
+        });
+        Ok(XidResponse::DelegateOk)
+    })
+}
+
+#[update(name = "revokeDelegate", guard="is_authorized")]
+#[candid_method(update, rename = "revokeDelegate")]
+async fn revoke_delegate(arg : Principal) -> Result<XidResponse, XidError> {
+    STATE.with(|s| remove_delegate(s, &arg.to_text()))
+}
+
+// 撤销立即生效, 后续调用的guard不再通过
+fn remove_delegate(s : &State, principal : &str) -> Result<XidResponse, XidError> {
+    match s.delegates.borrow_mut().remove(principal) {
+        Some(_) => Ok(XidResponse::RevokeOk),
+        None => Err(XidError::DataNotExist),
+    }
+}
+
+#[query(name = "getDelegates", guard="is_authorized")]
+#[candid_method(query, rename = "getDelegates")]
+fn get_delegates() -> Vec<Delegate> {
+    STATE.with(|s| {
+        s.delegates.borrow().values().cloned().collect()
+    })
+}
+
+// xid owner发起所有权转移, 新owner须在deadline前调用acceptOwner
+#[update(name = "proposeOwner", guard="is_authorized")]
+#[candid_method(update, rename = "proposeOwner")]
+async fn propose_owner(new_owner : Principal, deadline : u64) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        if deadline <= ic_cdk::api::time() || *s.pub_key.borrow() == new_owner.to_text() {
+            return Err(XidError::FieldOutOfRange);
+        };
+        *s.pending_owner.borrow_mut() = Some(OwnerTransfer {
+            new_owner: new_owner.to_text(),
+            deadline,
+        });
+        Ok(XidResponse::ProposeOk)
+    })
+}
+
+#[update(name = "cancelOwnerTransfer", guard="is_authorized")]
+#[candid_method(update, rename = "cancelOwnerTransfer")]
+async fn cancel_owner_transfer() -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        match s.pending_owner.borrow_mut().take() {
+            Some(_) => Ok(XidResponse::CancelOk),
+            None => Err(XidError::DataNotExist),
+        }
+    })
+}
+
+#[query(name = "getPendingOwner")]
+#[candid_method(query, rename = "getPendingOwner")]
+fn get_pending_owner() -> Option<OwnerTransfer> {
+    STATE.with(|s| {
+        s.pending_owner.borrow().clone()
+    })
+}
+
+// 新owner调用接受所有权
+#[update(name = "acceptOwner", guard="is_pending_owner")]
+#[candid_method(update, rename = "acceptOwner")]
+async fn accept_owner() -> Result<XidResponse, XidError> {
+    let now = ic_cdk::api::time();
+    // 调用transferXid前取出待转移状态, 失败时放回
+    let transfer = STATE.with(|s| {
+        check_not_transferring(s)?;
+        match s.pending_owner.borrow_mut().take() {
+            Some(t) if t.deadline < now => Err(XidError::TransferExpired),
+            Some(t) => Ok(t),
+            None => Err(XidError::DataNotExist),
+        }
+    })?;
+    let result = change_owner(caller()).await;
+    if result.is_err() {
+        STATE.with(|s| *s.pending_owner.borrow_mut() = Some(transfer));
+    };
+    result
+}
+
+// transferXid调用期间不可修改待转移与恢复状态, 也不可发起另一次转移
+fn check_not_transferring(s : &State) -> Result<(), XidError> {
+    if *s.transferring.borrow() {
+        return Err(XidError::TransferInProgress);
+    };
+    Ok(())
+}
+
+// 更新xid center中的owner映射, 成功后改写本地owner
+async fn change_owner(new_owner : Principal) -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        *s.transferring.borrow_mut() = true;
+        Ok(())
+    })?;
+    let xid_center = Principal::from_text("sgdrt-caaaa-aaaal-qbola-cai").unwrap();
+    let result = match ic::call::<_, (Result<(), XidCenterError>, ), _>(
+        xid_center,
+        "transferXid",
+        (&new_owner, )
+    ).await {
+        Ok((Ok(_), )) => Ok(()),
+        Ok((Err(XidCenterError::XidNotExist), )) => Err(XidError::XidNotExist),
+        Ok((Err(XidCenterError::XidExist), )) => Err(XidError::XidExist),
+        _ => Err(XidError::XidCNoNameErr),
+    };
+    STATE.with(|s| *s.transferring.borrow_mut() = false);
+    result?;
+    pages::invalidate();
+    STATE.with(|s| {
+        let owner = new_owner.to_text();
+        *s.pub_key.borrow_mut() = owner.clone();
+        *s.pending_owner.borrow_mut() = None;
+        *s.recovery.borrow_mut() = None;
+        s.delegates.borrow_mut().clear();
+        s.ic_pending.borrow_mut().clear();
+        for store in s.stores.borrow_mut().values_mut() {
+            for item in store.values_mut() {
+                item.owner = owner.clone();
+            }
+        }
+        for entry in s.trash.borrow_mut().values_mut() {
+            entry.item.owner = owner.clone();
+        }
+    });
+    Ok(XidResponse::TransferOk)
+}
+
+// xid owner设置守护者及m-of-n阈值, 会取消进行中的恢复
+#[update(name = "setRecoveryConfig", guard="is_authorized")]
+#[candid_method(update, rename = "setRecoveryConfig")]
+async fn set_recovery_config(mut arg : RecoveryConfig) -> Result<XidResponse, XidError> {
+    arg.guardians.sort();
+    arg.guardians.dedup();
+    recovery::check_config(&arg)?;
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        let pub_key = s.pub_key.borrow();
+        for g in arg.guardians.iter() {
+            match g {
+                Guardian::Principal(p) => {
+                    if *pub_key == p.to_text() {
+                        return Err(XidError::FieldOutOfRange);
+                    };
+                },
+                Guardian::Identity(id) => {
+                    if !is_bound(&s.ids.borrow(), id) {
+                        return Err(XidError::IDNotExist);
+                    };
+                },
+            };
+        }
+        *s.recovery_config.borrow_mut() = Some(arg);
+        *s.recovery.borrow_mut() = None;
+        Ok(XidResponse::ConfigOk)
+    })
+}
+
+#[query(name = "getRecoveryConfig")]
+#[candid_method(query, rename = "getRecoveryConfig")]
+fn get_recovery_config() -> Option<RecoveryConfig> {
+    let full = can_manage_identity().is_ok();
+    STATE.with(|s| {
+        let mut config = s.recovery_config.borrow().clone()?;
+        visible_guardians(s, full, &mut config.guardians);
+        Some(config)
+    })
+}
+
+#[query(name = "getRecovery")]
+#[candid_method(query, rename = "getRecovery")]
+fn get_recovery() -> Option<RecoveryRequest> {
+    let full = can_manage_identity().is_ok();
+    STATE.with(|s| {
+        let mut recovery = s.recovery.borrow().clone()?;
+        visible_guardians(s, full, &mut recovery.approvals);
+        Some(recovery)
+    })
+}
+
+// principal守护者(或已绑定的ic身份)调用, 审批将owner替换为new_owner
+#[update(name = "approveRecovery")]
+#[candid_method(update, rename = "approveRecovery")]
+async fn approve_recovery(new_owner : Principal) -> Result<XidResponse, XidError> {
+    let caller = caller();
+    let guardian = STATE.with(|s| {
+        let config = s.recovery_config.borrow();
+        let config = match &*config {
+            Some(c) => c,
+            None => return None,
+        };
+        let ic_id = SimpleId {
+            platform: "ic".to_string(),
+            identity: caller.to_text(),
+        };
+        config.guardians.iter().find(|g| match g {
+            Guardian::Principal(p) => *p == caller,
+            Guardian::Identity(id) => *id == ic_id,
+        }).cloned()
+    });
+    match guardian {
+        Some(g) => add_recovery_approval(g, new_owner, None),
+        None => Err(XidError::NotGuardian),
+    }
+}
+
+// 身份守护者通过签名消息审批, payload.action须为 "recover:<canister id>:<new owner principal>:<nonce>",
+// nonce为进行中请求的start_time, 发起新请求时为0
+#[update(name = "approveRecoveryByProof")]
+#[candid_method(update, rename = "approveRecoveryByProof")]
+async fn approve_recovery_by_proof(msg : MsgIn) -> Result<XidResponse, XidError> {
+    let verify = Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap();
+    let pay_load = match ic::call::<_, (Result<Payload, VerifyError>, ), _>(
+        verify,
+        "msg_in",
+        (&msg, )
+    ).await {
+        Ok((Ok(p), )) => p,
+        _ => return Err(XidError::NotGuardian),
+    };
+    let (new_owner, nonce) = match recovery::parse_action(&pay_load.action, &ic_cdk::id()) {
+        Some(action) => action,
+        None => return Err(XidError::FieldOutOfRange),
+    };
+    add_recovery_approval(Guardian::Identity(SimpleId {
+        platform: pay_load.platform,
+        identity: pay_load.identity,
+    }), new_owner, Some((nonce, pay_load.created_at)))
+}
+
+// xid owner在否决期内否决恢复
+#[update(name = "vetoRecovery", guard="is_authorized")]
+#[candid_method(update, rename = "vetoRecovery")]
+async fn veto_recovery() -> Result<XidResponse, XidError> {
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        match s.recovery.borrow_mut().take() {
+            Some(_) => Ok(XidResponse::VetoOk),
+            None => Err(XidError::DataNotExist),
+        }
+    })
+}
+
+// 否决期结束后任何人可调用, 走所有权转移流程
+#[update(name = "executeRecovery")]
+#[candid_method(update, rename = "executeRecovery")]
+async fn execute_recovery() -> Result<XidResponse, XidError> {
+    // 调用transferXid前取出恢复请求, 否决与新的审批无法作用于执行中的请求; 失败时放回
+    let request = STATE.with(|s| {
+        check_not_transferring(s)?;
+        let config = s.recovery_config.borrow();
+        let mut recovery = s.recovery.borrow_mut();
+        match (&*config, &*recovery) {
+            (Some(c), Some(r)) if recovery::is_ready(c, r, ic_cdk::api::time()) => Ok(recovery.take().unwrap()),
+            (Some(_), Some(_)) => Err(XidError::RecoveryNotReady),
+            _ => Err(XidError::DataNotExist),
+        }
+    })?;
+    let result = match Principal::from_text(&request.new_owner) {
+        Ok(new_owner) => change_owner(new_owner).await,
+        Err(_) => Err(XidError::FieldOutOfRange),
+    };
+    if result.is_err() {
+        STATE.with(|s| *s.recovery.borrow_mut() = Some(request));
+    };
+    result
+}
+
+// proof为签名审批的(nonce, 签名创建时间)
+fn add_recovery_approval(
+    guardian : Guardian,
+    new_owner : Principal,
+    proof : Option<(u64, u64)>,
+) -> Result<XidResponse, XidError> {
+    let now = ic_cdk::api::time();
+    STATE.with(|s| {
+        check_not_transferring(s)?;
+        let config = s.recovery_config.borrow();
+        let config = match &*config {
+            Some(c) => c,
+            None => return Err(XidError::DataNotExist),
+        };
+        if !config.guardians.contains(&guardian) {
+            return Err(XidError::NotGuardian);
+        };
+        if let Guardian::Identity(id) = &guardian {
+            if !is_bound(&s.ids.borrow(), id) {
+                return Err(XidError::NotGuardian);
+            };
+        };
+        if *s.pub_key.borrow() == new_owner.to_text() {
+            return Err(XidError::FieldOutOfRange);
+        };
+        let mut recovery = s.recovery.borrow_mut();
+        if let Some((nonce, created_at)) = proof {
+            recovery::check_nonce(config, recovery.as_ref(), nonce, created_at, now)?;
+        };
+        let expired = match &*recovery {
+            Some(r) => recovery::is_expired(config, r, now),
+            None => true,
+        };
+        if expired {
+            *recovery = Some(RecoveryRequest {
+                new_owner: new_owner.to_text(),
+                start_time: now,
+                approvals: Vec::new(),
+                approved_time: None,
+            });
+        };
+        let r = recovery.as_mut().unwrap();
+        if r.new_owner != new_owner.to_text() {
+            return Err(XidError::RecoveryPending);
+        };
+        if !r.approvals.contains(&guardian) {
+            r.approvals.push(guardian);
+        };
+        if r.approved_time.is_none() && r.approvals.len() >= config.threshold as usize {
+            r.approved_time = Some(now);
+        };
+        Ok(XidResponse::ApproveOk)
+    })
+}
+
+fn is_bound(ids : &BTreeSet<ID>, id : &SimpleId) -> bool {
+    ids.contains(&ID {
+        platform: id.platform.clone(),
+        identity: id.identity.clone(),
+        bind_time: 0,
+    })
+}
+
+fn is_authorized() -> Result<(), String> {
+    STATE.with(|s| {
+        if is_owner(s, &caller().to_text()) {
+            Ok(())
+        } else {
+            Err("Caller is not authorized".to_string())
+        }
+    })
+}
+
+fn has_scope(scope : Scope) -> Result<(), String> {
+    let caller = caller().to_text();
+    STATE.with(|s| {
+        if scope_allows(s, &caller, &scope, ic_cdk::api::time()) {
+            Ok(())
+        } else {
+            Err("Caller is not authorized".to_string())
+        }
+    })
+}
+
+fn is_owner(s : &State, caller : &str) -> bool {
+    *s.pub_key.borrow() == caller
+}
+
+// owner或持有对应scope且未过期的delegate
+fn scope_allows(s : &State, caller : &str, scope : &Scope, now : u64) -> bool {
+    is_owner(s, caller) || s.delegates.borrow().get(caller)
+        .is_some_and(|d| d.scopes.contains(scope) && d.expiry.is_none_or(|e| e > now))
+}
+
+fn can_write_content() -> Result<(), String> {
+    has_scope(Scope::ContentWrite)
+}
+
+fn can_write_profile() -> Result<(), String> {
+    has_scope(Scope::ProfileWrite)
+}
+
+fn can_manage_identity() -> Result<(), String> {
+    has_scope(Scope::IdentityManage)
+}
+
+fn is_pending_owner() -> Result<(), String> {
+    STATE.with(|s| {
+        match &*s.pending_owner.borrow() {
+            Some(t) if t.new_owner == caller().to_text() => Ok(()),
+            _ => Err("Caller is not authorized".to_string()),
+        }
+    })
+}
+
+fn is_ic_authorized() -> Result<(), String> {
+    STATE.with(|s| {
+        match s.ic_pending.borrow().get(&caller().to_text()) {
+            Some(c) if c.deadline >= ic_cdk::api::time() => Ok(()),
+            _ => Err("Caller is not authorized".to_string()),
+        }
+    })
+}
+
+fn to_hex(bytes : &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s : &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    };
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn do_clear() {
+    STATE.with(|s| {
+        s.pub_key.borrow_mut().clear();
+        s.name.borrow_mut().clear();
+        s.ids.borrow_mut().clear();
+        s.ic_pending.borrow_mut().clear();
+        s.delegates.borrow_mut().clear();
+        s.pending_owner.borrow_mut().take();
+        s.recovery_config.borrow_mut().take();
+        s.recovery.borrow_mut().take();
+        s.avatar_url.borrow_mut().clear();
+        s.stores.borrow_mut().clear();
+        s.schemas.borrow_mut().clear();
+        s.timeline.borrow_mut().clear();
+        s.search_index.borrow_mut().clear();
+        s.proofs.borrow_mut().clear();
+        s.validity.borrow_mut().clear();
+        s.revoked.borrow_mut().clear();
+        s.stale_notified.borrow_mut().clear();
+        s.visibility.borrow_mut().clear();
+        s.presentations.borrow_mut().clear();
+        s.main_id_policy.borrow_mut().take();
+        *s.main_id_time.borrow_mut() = 0;
+        s.tags.borrow_mut().clear();
+        s.collections.borrow_mut().clear();
+        s.versions.borrow_mut().clear();
+        s.trash.borrow_mut().clear();
+        s.media.borrow_mut().clear();
+        s.upload_sessions.borrow_mut().clear();
+    })
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let stable_state : StableState = STATE.with(|s| StableState{
+        pub_key: s.pub_key.take(),
+        name: s.name.take(),
+        main_id: s.main_id.take(),
+        ids: s.ids.take(),
+        ic_pending: Some(s.ic_pending.take()),
+        delegates: Some(s.delegates.take()),
+        pending_owner: s.pending_owner.take(),
+        recovery_config: s.recovery_config.take(),
+        recovery: s.recovery.take(),
+        avatar_url: s.avatar_url.take(),
+        avatar: s.avatar.take(),
+        stores: Some(s.stores.take()),
+        schemas: Some(s.schemas.take()),
+        proofs: Some(s.proofs.take()),
+        validity: Some(s.validity.take()),
+        revoked: Some(s.revoked.take()),
+        stale_notified: Some(s.stale_notified.take()),
+        visibility: Some(s.visibility.take()),
+        presentations: Some(s.presentations.take()),
+        main_id_policy: s.main_id_policy.take(),
+        main_id_time: Some(s.main_id_time.take()),
+        tags: Some(s.tags.take()),
+        collections: Some(s.collections.take()),
+        versions: Some(s.versions.take()),
+        trash: Some(s.trash.take()),
+        media: Some(s.media.take()),
+    });
+    ic::stable_store((stable_state, )).expect("failed to save stable state");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    do_clear();
+    // 旧版时间字段为字符串, 解码失败时按旧格式读取并迁移
+    let stable_state : StableState = match ic::stable_restore::<(StableState, )>() {
+        Ok((state, )) => state,
+        Err(_) => {
+            let (legacy, ) : (LegacyStableState, ) =
+                ic::stable_restore().expect("failed to restore stable state");
+            legacy.migrate()
+        },
+    };
+
+    STATE.with(|s| {
+        s.pub_key.replace(stable_state.pub_key);
+        s.name.replace(stable_state.name);
+        s.main_id.replace(stable_state.main_id);
+        s.ids.replace(stable_state.ids);
+        s.ic_pending.replace(stable_state.ic_pending.unwrap_or_default());
+        s.delegates.replace(stable_state.delegates.unwrap_or_default());
+        s.pending_owner.replace(stable_state.pending_owner);
+        s.recovery_config.replace(stable_state.recovery_config);
+        s.recovery.replace(stable_state.recovery);
+        s.avatar_url.replace(stable_state.avatar_url);
+        s.avatar.replace(stable_state.avatar);
+        s.stores.replace(stable_state.stores.unwrap_or_default());
+        s.schemas.replace(stable_state.schemas.unwrap_or_default());
+        s.proofs.replace(stable_state.proofs.unwrap_or_default());
+        s.validity.replace(stable_state.validity.unwrap_or_default());
+        s.revoked.replace(stable_state.revoked.unwrap_or_default());
+        s.stale_notified.replace(stable_state.stale_notified.unwrap_or_default());
+        s.visibility.replace(stable_state.visibility.unwrap_or_default());
+        s.presentations.replace(stable_state.presentations.unwrap_or_default());
+        s.main_id_policy.replace(stable_state.main_id_policy);
+        s.main_id_time.replace(stable_state.main_id_time.unwrap_or_default());
+        s.tags.replace(stable_state.tags.unwrap_or_default());
+        s.collections.replace(stable_state.collections.unwrap_or_default());
+        s.versions.replace(stable_state.versions.unwrap_or_default());
+        s.trash.replace(stable_state.trash.unwrap_or_default());
+        s.media.replace(stable_state.media.unwrap_or_default());
+    });
+    rebuild_indexes();
+    // 升级会清除定时器, last_purge不持久化, 立即执行一次清理
+    timer::schedule(ic_cdk::api::time());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::utils::{service_compatible, CandidSource};
+    use std::path::Path;
+    use types::StoreFilter;
+
+    // xid.did为手工维护, 与candid_method生成的接口需双向兼容
+    #[test]
+    fn did_matches_exported_interface() {
+        candid::export_service!();
+        let generated = __export_service();
+        let did = Path::new(env!("CARGO_MANIFEST_DIR")).join("xid.did");
+        service_compatible(CandidSource::Text(&generated), CandidSource::File(&did))
+            .expect("exported interface is not a subtype of xid.did");
+        service_compatible(CandidSource::File(&did), CandidSource::Text(&generated))
+            .expect("xid.did is not a subtype of the exported interface");
+    }
+
+    fn pending(n : usize, requester : Option<Principal>, deadline : u64) -> BTreeMap<String, IcChallenge> {
+        (0..n).map(|i| {
+            let principal = Principal::from_slice(&[i as u8, requester.map_or(0, |p| p.as_slice()[0])]).to_text();
+            (principal.clone(), IcChallenge { principal, nonce: String::new(), deadline, requester })
+        }).collect()
+    }
+
+    #[test]
+    fn ic_pending_is_capped_per_caller() {
+        let manager = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        let target = Principal::anonymous().to_text();
+        let mut ic_pending = pending(MAX_IC_PENDING_PER_CALLER - 1, Some(manager), 10);
+        assert!(check_ic_pending(&ic_pending, &manager, &target, 10).is_ok());
+        ic_pending.extend(pending(MAX_IC_PENDING_PER_CALLER, Some(manager), 10));
+        assert!(matches!(check_ic_pending(&ic_pending, &manager, &target, 10), Err(VerifyError::TooManyChallenges)));
+        assert!(check_ic_pending(&ic_pending, &other, &target, 10).is_ok());
+        // 过期的与同一principal的challenge不计入
+        assert!(check_ic_pending(&ic_pending, &manager, &target, 11).is_ok());
+        let existing = ic_pending.keys().next().unwrap().clone();
+        assert!(check_ic_pending(&ic_pending, &manager, &existing, 10).is_ok());
+    }
+
+    #[test]
+    fn ic_pending_is_capped_in_total() {
+        let mut ic_pending = pending(MAX_IC_PENDING - 1, None, 10);
+        let caller = Principal::from_slice(&[1]);
+        let target = Principal::anonymous().to_text();
+        assert!(check_ic_pending(&ic_pending, &caller, &target, 10).is_ok());
+        ic_pending.extend(pending(1, Some(Principal::from_slice(&[2])), 10));
+        assert!(matches!(check_ic_pending(&ic_pending, &caller, &target, 10), Err(VerifyError::TooManyChallenges)));
+    }
+
+    const NOW : u64 = 1_700_000_000_000_000_000;
+
+    fn key(uuid : &str) -> ContentUuid {
+        ContentUuid { content_type: ContentType::OffChain, uuid: uuid.to_string() }
+    }
+
+    // 含n条链下内容与一个空合集
+    fn content_state(n : usize) -> State {
+        let s = State::default();
+        let mut stores = s.stores.borrow_mut();
+        let store = stores.entry(ContentType::OffChain).or_default();
+        for i in 0..n {
+            let uuid = format!("{:04}", i);
+            store.insert(uuid.clone(), Storage {
+                owner: String::new(),
+                uuid,
+                content_type: ContentType::OffChain,
+                content: Contents::OffChainContent(Default::default()),
+                d_platform: String::new(),
+                is_minted: false,
+                mint_time: None,
+                upload_time: NOW + i as u64,
+                edit_time: None,
+            });
+        }
+        drop(stores);
+        s.collections.borrow_mut().insert("c".to_string(), Collection {
+            name: "c".to_string(),
+            description: String::new(),
+            is_public: true,
+            items: vec![],
+            create_time: NOW,
+            update_time: NOW,
+        });
+        s
+    }
+
+    fn collection_items(s : &State) -> Vec<String> {
+        s.collections.borrow()["c"].items.iter().map(|k| k.uuid.clone()).collect()
+    }
+
+    // 各接口的guard: 接口名 -> guard函数名
+    fn endpoint_guards() -> BTreeMap<String, String> {
+        let quoted = |line : &str, key : &str| -> Option<String> {
+            let rest = &line[line.find(key)? + key.len()..];
+            let rest = rest.trim_start_matches([' ', '=']).strip_prefix('"')?;
+            Some(rest[..rest.find('"')?].to_string())
+        };
+        include_str!("lib.rs").lines()
+            .filter(|line| line.starts_with("#[update(") || line.starts_with("#[query("))
+            .filter_map(|line| Some((quoted(line, "name")?, quoted(line, "guard")?)))
+            .collect()
+    }
+
+    // guard对调用者的判定, 与scope无关的guard为None
+    fn guard_allows(s : &State, guard : &str, caller : &str, now : u64) -> Option<bool> {
+        match guard {
+            "is_authorized" => Some(is_owner(s, caller)),
+            "can_write_content" => Some(scope_allows(s, caller, &Scope::ContentWrite, now)),
+            "can_write_profile" => Some(scope_allows(s, caller, &Scope::ProfileWrite, now)),
+            "can_manage_identity" => Some(scope_allows(s, caller, &Scope::IdentityManage, now)),
+            _ => None,
+        }
+    }
+
+    fn scope_guard(scope : &Scope) -> &'static str {
+        match scope {
+            Scope::ContentWrite => "can_write_content",
+            Scope::ProfileWrite => "can_write_profile",
+            Scope::IdentityManage => "can_manage_identity",
+        }
+    }
+
+    fn delegate_state(scopes : Vec<Scope>, expiry : Option<u64>) -> (State, String) {
+        let s = State::default();
+        *s.pub_key.borrow_mut() = Principal::from_slice(&[1]).to_text();
+        let delegate = Principal::from_slice(&[2]).to_text();
+        s.delegates.borrow_mut().insert(delegate.clone(), Delegate {
+            principal: delegate.clone(),
+            scopes,
+            expiry,
+            add_time: NOW,
+        });
+        (s, delegate)
+    }
+
+    #[test]
+    fn owner_only_endpoints() {
+        let guards = endpoint_guards();
+        for name in ["addDelegate", "revokeDelegate", "getDelegates", "proposeOwner", "cancelOwnerTransfer"] {
+            assert_eq!(guards[name], "is_authorized", "{}", name);
+        }
+        assert_eq!(guards["uploadStore"], "can_write_content");
+        assert_eq!(guards["uploadAvatar"], "can_write_profile");
+        assert_eq!(guards["changeMainId"], "can_manage_identity");
+
+        let (s, delegate) = delegate_state(vec![Scope::ContentWrite, Scope::ProfileWrite, Scope::IdentityManage], None);
+        let owner = s.pub_key.borrow().clone();
+        for (name, guard) in guards.iter() {
+            if let Some(allowed) = guard_allows(&s, guard, &delegate, NOW) {
+                assert_eq!(allowed, guard != "is_authorized", "{}", name);
+                assert_eq!(guard_allows(&s, guard, &owner, NOW), Some(true), "{}", name);
+            };
+        }
+    }
+
+    #[test]
+    fn each_scope_allows_exactly_its_endpoints() {
+        let guards = endpoint_guards();
+        for scope in [Scope::ContentWrite, Scope::ProfileWrite, Scope::IdentityManage] {
+            let (s, delegate) = delegate_state(vec![scope], None);
+            assert!(guards.values().any(|g| g == scope_guard(&scope)));
+            for (name, guard) in guards.iter() {
+                if let Some(allowed) = guard_allows(&s, guard, &delegate, NOW) {
+                    assert_eq!(allowed, guard == scope_guard(&scope), "{:?} {}", scope, name);
+                };
+            }
+            // 其他principal不受影响
+            let stranger = Principal::from_slice(&[3]).to_text();
+            assert!(!scope_allows(&s, &stranger, &scope, NOW));
+        }
+    }
+
+    #[test]
+    fn revocation_and_expiry_take_effect() {
+        let (s, delegate) = delegate_state(vec![Scope::ContentWrite], Some(NOW + 10));
+        assert!(scope_allows(&s, &delegate, &Scope::ContentWrite, NOW + 9));
+        assert!(!scope_allows(&s, &delegate, &Scope::ContentWrite, NOW + 10));
+
+        let (s, delegate) = delegate_state(vec![Scope::ContentWrite], None);
+        assert!(scope_allows(&s, &delegate, &Scope::ContentWrite, u64::MAX));
+        remove_delegate(&s, &delegate).unwrap();
+        assert!(!scope_allows(&s, &delegate, &Scope::ContentWrite, NOW));
+        assert!(matches!(remove_delegate(&s, &delegate), Err(XidError::DataNotExist)));
+        assert!(scope_allows(&s, &s.pub_key.borrow(), &Scope::ContentWrite, NOW));
+    }
+
+    fn list_args(cursor : Option<String>, limit : u64, order : SortOrder, filter : StoreFilter) -> ListArgs {
+        ListArgs { content_type: ContentType::OffChain, cursor, limit, filter, order }
+    }
+
+    fn uuids(page : &StorePage) -> Vec<&str> {
+        page.items.iter().map(|item| item.uuid.as_str()).collect()
+    }
+
+    #[test]
+    fn cursor_decoding() {
+        assert_eq!(from_hex(&to_hex("a/é".as_bytes())), Some("a/é".as_bytes().to_vec()));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("é0"), None);
+
+        let key = ContentUuid { content_type: ContentType::Custom("note".to_string()), uuid: "a/b".to_string() };
+        assert_eq!(decode_content_cursor(&encode_content_cursor(&key)), Some(key));
+        assert_eq!(decode_content_cursor(&to_hex(b"no-separator")), None);
+        assert_eq!(decode_content_cursor(&to_hex(&[0xff, b'/'])), None);
+
+        let s = content_state(1);
+        for cursor in ["abc", "zz", "ff"] {
+            let args = list_args(Some(cursor.to_string()), 1, SortOrder::Asc, StoreFilter::default());
+            assert!(matches!(list_items(&s, args), Err(XidError::FieldOutOfRange)), "{}", cursor);
+        }
+    }
+
+    #[test]
+    fn list_pages_until_empty() {
+        let s = content_state(5);
+        for (order, expected) in [(SortOrder::Asc, ["0000", "0001", "0002", "0003", "0004"]),
+                                  (SortOrder::Desc, ["0004", "0003", "0002", "0001", "0000"])] {
+            let mut cursor = None;
+            let mut seen = Vec::new();
+            loop {
+                let page = list_items(&s, list_args(cursor, 2, order.clone(), StoreFilter::default())).unwrap();
+                seen.extend(uuids(&page).into_iter().map(String::from));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                };
+            }
+            assert_eq!(seen, expected);
+        }
+        // 恰好在末尾结束的一页仍带游标, 下一页为空且无游标
+        let page = list_items(&s, list_args(None, 5, SortOrder::Asc, StoreFilter::default())).unwrap();
+        let last = list_items(&s, list_args(page.next_cursor, 5, SortOrder::Asc, StoreFilter::default())).unwrap();
+        assert!(last.items.is_empty() && last.next_cursor.is_none());
+
+        let args = ListArgs { content_type: ContentType::Twitter, ..list_args(None, 5, SortOrder::Asc, StoreFilter::default()) };
+        assert!(list_items(&s, args).unwrap().items.is_empty());
+        assert!(list_items(&s, list_args(None, 0, SortOrder::Asc, StoreFilter::default())).is_err());
+        assert!(list_items(&s, list_args(None, MAX_PAGE_SIZE + 1, SortOrder::Asc, StoreFilter::default())).is_err());
+    }
+
+    #[test]
+    fn store_slice_is_empty_past_the_end() {
+        let s = content_state(3);
+        assert_eq!(store_slice(&s, &ContentType::OffChain, 1, 10).len(), 2);
+        assert!(store_slice(&s, &ContentType::OffChain, 3, 10).is_empty());
+        assert!(store_slice(&s, &ContentType::OffChain, usize::MAX, 10).is_empty());
+        assert!(store_slice(&s, &ContentType::Twitter, 0, 10).is_empty());
+    }
+
+    #[test]
+    fn store_filter() {
+        let s = content_state(4);
+        {
+            let mut stores = s.stores.borrow_mut();
+            let store = stores.get_mut(&ContentType::OffChain).unwrap();
+            for uuid in ["0001", "0003"] {
+                let item = store.get_mut(uuid).unwrap();
+                item.is_minted = true;
+                item.mint_time = Some(NOW + 10);
+                item.d_platform = "dsocial".to_string();
+            }
+        }
+        let list = |filter : StoreFilter| {
+            let page = list_items(&s, list_args(None, 10, SortOrder::Asc, filter)).unwrap();
+            uuids(&page).into_iter().map(String::from).collect::<Vec<String>>()
+        };
+        assert_eq!(list(StoreFilter::default()).len(), 4);
+        assert_eq!(list(StoreFilter { is_minted: Some(true), ..Default::default() }), ["0001", "0003"]);
+        assert_eq!(list(StoreFilter { is_minted: Some(false), ..Default::default() }), ["0000", "0002"]);
+        assert_eq!(list(StoreFilter { d_platform: Some("dsocial".to_string()), ..Default::default() }), ["0001", "0003"]);
+        assert_eq!(list(StoreFilter { d_platform: Some(String::new()), ..Default::default() }), ["0000", "0002"]);
+        // 时间区间包含两端
+        let uploaded = TimeRange { from: Some(NOW + 1), to: Some(NOW + 2) };
+        assert_eq!(list(StoreFilter { uploaded: Some(uploaded), ..Default::default() }), ["0001", "0002"]);
+        // 未铸造的内容不匹配任何铸造时间区间
+        let minted = TimeRange { from: None, to: None };
+        assert_eq!(list(StoreFilter { minted: Some(minted), ..Default::default() }), ["0001", "0003"]);
+        let minted = TimeRange { from: Some(NOW + 11), to: None };
+        assert!(list(StoreFilter { minted: Some(minted), ..Default::default() }).is_empty());
+
+        // 过滤后不足一页时不返回游标
+        let filter = StoreFilter { is_minted: Some(true), ..Default::default() };
+        let page = list_items(&s, list_args(None, 3, SortOrder::Asc, filter)).unwrap();
+        assert!(page.next_cursor.is_none());
+    }
+
+    fn bound(platform : &str, bind_time : u64) -> ID {
+        ID { platform: platform.to_string(), identity: format!("{}-user", platform), bind_time }
+    }
+
+    // 依次绑定ids, 与绑定流程一样在无主身份时自动设置
+    fn identity_state(policy : Option<MainIdPolicy>, ids : &[ID]) -> State {
+        let s = State::default();
+        *s.main_id_policy.borrow_mut() = policy;
+        for id in ids {
+            assign_first_main_id(&s, id);
+            s.ids.borrow_mut().insert(id.clone());
+        }
+        s
+    }
+
+    fn policy(on_unbind : MainIdUnbind, cooldown : u64) -> Option<MainIdPolicy> {
+        Some(MainIdPolicy { platforms: vec!["github".to_string(), "twitter".to_string()], on_unbind, cooldown })
+    }
+
+    #[test]
+    fn first_binding_does_not_start_cooldown() {
+        let s = identity_state(policy(MainIdUnbind::Promote, 1000), &[bound("github", NOW), bound("twitter", NOW)]);
+        assert_eq!(*s.main_id.borrow(), bound("github", NOW));
+        assert_eq!(*s.main_id_time.borrow(), 0);
+        assert!(!main_id_locked(&s, NOW));
+
+        // 主动变更后cooldown内锁定
+        set_main_id(&s, bound("twitter", NOW), NOW);
+        assert!(main_id_locked(&s, NOW + 999));
+        assert!(!main_id_locked(&s, NOW + 1000));
+        assert!(matches!(unbind(&s, &bound("twitter", NOW), NOW + 1), Err(XidError::MainIdBan)));
+        assert!(unbind(&s, &bound("twitter", NOW), NOW + 1000).is_ok());
+    }
+
+    #[test]
+    fn unbind_without_policy_blanks_main_id() {
+        let s = identity_state(None, &[bound("github", NOW), bound("twitter", NOW + 1)]);
+        assert_eq!(*s.main_id.borrow(), bound("github", NOW));
+        unbind(&s, &bound("github", NOW), NOW + 2).unwrap();
+        // 不自动选出继任者, 直到下次绑定或changeMainId
+        assert_eq!(*s.main_id.borrow(), ID::default());
+        assert!(s.ids.borrow().contains(&bound("twitter", NOW + 1)));
+        assert!(s.revoked.borrow().contains_key(&SimpleId { platform: "github".to_string(), identity: "github-user".to_string() }));
+        assign_first_main_id(&s, &bound("email", NOW + 3));
+        assert_eq!(s.main_id.borrow().platform, "email");
+        assert!(matches!(unbind(&s, &bound("github", NOW), NOW + 4), Err(XidError::IDNotExist)));
+    }
+
+    #[test]
+    fn unbind_follows_policy() {
+        let ids = [bound("email", NOW), bound("twitter", NOW + 1), bound("github", NOW + 2)];
+        let s = identity_state(policy(MainIdUnbind::Promote, 1000), &ids);
+        assert_eq!(s.main_id.borrow().platform, "twitter");
+        unbind(&s, &bound("twitter", NOW + 1), NOW + 3).unwrap();
+        // 按策略平台顺序选出继任者, 自动选择不开始cooldown
+        assert_eq!(s.main_id.borrow().platform, "github");
+        assert!(!main_id_locked(&s, NOW + 3));
+
+        let s = identity_state(policy(MainIdUnbind::Refuse, 0), &ids);
+        assert!(matches!(unbind(&s, &bound("twitter", NOW + 1), NOW + 3), Err(XidError::MainIdBan)));
+        assert!(unbind(&s, &bound("email", NOW), NOW + 3).is_ok());
+        assert_eq!(s.main_id.borrow().platform, "twitter");
+    }
+
+    fn timeline_item(content_type : ContentType, uuid : &str, post_time : u64, upload_time : u64) -> Storage {
+        let content = match content_type {
+            ContentType::Twitter => Contents::TwitterContent(types::TwitterContent {
+                url: String::new(),
+                text_content: format!("tweet {}", uuid),
+                text_url: String::new(),
+                image_urls: vec![],
+                video_url: String::new(),
+                post_time,
+            }),
+            ContentType::OffChain => Contents::OffChainContent(types::OffChainContent {
+                text_content: format!("hello timeline {}", uuid),
+                ..Default::default()
+            }),
+            ContentType::Custom(_) => Contents::Custom(types::CustomContent {
+                content_type: content_type.name().to_string(),
+                fields: vec![],
+            }),
+        };
+        Storage {
+            owner: String::new(),
+            uuid: uuid.to_string(),
+            content_type,
+            content,
+            d_platform: String::new(),
+            is_minted: false,
+            mint_time: None,
+            upload_time,
+            edit_time: None,
+        }
+    }
+
+    // 多条内容时间相同, 按(类型, uuid)区分; 推文缺少发布时间时退回上传时间
+    fn timeline_state() -> State {
+        let s = State::default();
+        let items = [
+            timeline_item(ContentType::Twitter, "b", NOW, NOW + 100),
+            timeline_item(ContentType::Twitter, "a", NOW, NOW + 200),
+            timeline_item(ContentType::Twitter, "z", 0, NOW),
+            timeline_item(ContentType::OffChain, "a/x", 0, NOW),
+            timeline_item(ContentType::OffChain, "c", 0, NOW + 1),
+            timeline_item(ContentType::Custom("note".to_string()), "1", 0, NOW - 1),
+        ];
+        for item in items {
+            s.stores.borrow_mut().entry(item.content_type.clone()).or_default().insert(item.uuid.clone(), item);
+        }
+        rebuild_content_indexes(&s);
+        s
+    }
+
+    fn timeline_keys(s : &State) -> Vec<TimelineKey> {
+        s.timeline.borrow().iter().cloned().collect()
+    }
+
+    #[test]
+    fn timeline_key_ordering() {
+        let s = timeline_state();
+        let key = |time, content_type : ContentType, uuid : &str| (time, content_type, uuid.to_string());
+        assert_eq!(timeline_keys(&s), vec![
+            key(NOW - 1, ContentType::Custom("note".to_string()), "1"),
+            key(NOW, ContentType::Twitter, "a"),
+            key(NOW, ContentType::Twitter, "b"),
+            key(NOW, ContentType::Twitter, "z"),
+            key(NOW, ContentType::OffChain, "a/x"),
+            key(NOW + 1, ContentType::OffChain, "c"),
+        ]);
+        for k in timeline_keys(&s) {
+            assert_eq!(decode_timeline_cursor(&encode_timeline_cursor(&k)), Some(k));
+        }
+        assert_eq!(decode_timeline_cursor(&to_hex(b"x/Twitter/a")), None);
+        assert_eq!(decode_timeline_cursor(&to_hex(b"1/Twitter")), None);
+    }
+
+    #[test]
+    fn timeline_pages_across_equal_timestamps() {
+        let s = timeline_state();
+        let expected : Vec<String> = timeline_keys(&s).into_iter().rev().map(|(_, _, uuid)| uuid).collect();
+        for limit in 1..=expected.len() as u64 + 1 {
+            let mut cursor = None;
+            let mut seen = Vec::new();
+            loop {
+                let page = timeline_page(&s, cursor, limit, None).unwrap();
+                assert!(page.items.len() as u64 <= limit);
+                seen.extend(page.items.into_iter().map(|item| item.uuid));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                };
+            }
+            assert_eq!(seen, expected, "limit {}", limit);
+        }
+        assert!(matches!(timeline_page(&s, Some("zz".to_string()), 1, None), Err(XidError::FieldOutOfRange)));
+        assert!(matches!(timeline_page(&s, None, 0, None), Err(XidError::FieldOutOfRange)));
+    }
+
+    #[test]
+    fn timeline_range_is_inclusive() {
+        let s = timeline_state();
+        let uuids = |range : TimeRange| -> Vec<String> {
+            timeline_page(&s, None, MAX_PAGE_SIZE, Some(range)).unwrap().items.into_iter().map(|item| item.uuid).collect()
+        };
+        assert_eq!(uuids(TimeRange { from: Some(NOW), to: Some(NOW) }), ["a/x", "z", "b", "a"]);
+        assert_eq!(uuids(TimeRange { from: Some(NOW + 1), to: None }), ["c"]);
+        assert_eq!(uuids(TimeRange { from: None, to: Some(NOW - 1) }), ["1"]);
+        assert!(uuids(TimeRange { from: Some(NOW + 2), to: None }).is_empty());
+        assert!(uuids(TimeRange { from: Some(NOW + 1), to: Some(NOW) }).is_empty());
+        assert_eq!(uuids(TimeRange { from: Some(0), to: Some(u64::MAX) }).len(), 6);
+
+        // 游标与区间上界取较小者
+        let page = timeline_page(&s, None, 2, Some(TimeRange { from: Some(NOW), to: Some(NOW) })).unwrap();
+        let rest = timeline_page(&s, page.next_cursor, 10, Some(TimeRange { from: Some(NOW), to: Some(NOW) })).unwrap();
+        assert_eq!(rest.items.iter().map(|item| item.uuid.as_str()).collect::<Vec<_>>(), ["b", "a"]);
+    }
+
+    // post_upgrade时时间线与全文索引不持久化, 由stores重建
+    #[test]
+    fn indexes_rebuild_from_stores() {
+        let s = timeline_state();
+        let before = timeline_keys(&s);
+        let terms = search::query_terms("timeline").unwrap();
+        assert_eq!(s.search_index.borrow().search(&terms).len(), 2);
+
+        *s.timeline.borrow_mut() = Default::default();
+        *s.search_index.borrow_mut() = Default::default();
+        assert!(timeline_page(&s, None, 10, None).unwrap().items.is_empty());
+        rebuild_content_indexes(&s);
+        assert_eq!(timeline_keys(&s), before);
+        assert_eq!(s.search_index.borrow().search(&terms).len(), 2);
+
+        // 旧索引中已不存在的内容在重建后消失
+        s.stores.borrow_mut().get_mut(&ContentType::OffChain).unwrap().remove("c");
+        rebuild_content_indexes(&s);
+        assert_eq!(timeline_keys(&s).len(), 5);
+        assert_eq!(s.search_index.borrow().search(&terms).len(), 1);
+        assert_eq!(timeline_page(&s, None, 1, None).unwrap().items[0].uuid, "a/x");
+    }
+
+    #[test]
+    fn tags_add_and_remove() {
+        let s = content_state(3);
+        assert!(matches!(add_tag(&s, "t".to_string(), vec![]), Err(XidError::FieldOutOfRange)));
+        assert!(matches!(add_tag(&s, "t".to_string(), vec![key("9999")]), Err(XidError::UuidNotExist)));
+        add_tag(&s, "t".to_string(), vec![key("0000"), key("0001"), key("0000")]).unwrap();
+        assert_eq!(s.tags.borrow()["t"].len(), 2);
+
+        remove_tag(&s, "t", &[key("0000"), key("0002")]).unwrap();
+        assert_eq!(s.tags.borrow()["t"].iter().collect::<Vec<_>>(), vec![&key("0001")]);
+        // 最后一条移除后标签一并删除
+        remove_tag(&s, "t", &[key("0001")]).unwrap();
+        assert!(s.tags.borrow().is_empty());
+        assert!(matches!(remove_tag(&s, "t", &[key("0001")]), Err(XidError::DataNotExist)));
+    }
+
+    #[test]
+    fn tags_are_capped() {
+        let s = content_state(1);
+        for i in 0..MAX_TAGS {
+            add_tag(&s, format!("t{}", i), vec![key("0000")]).unwrap();
+        }
+        assert!(matches!(add_tag(&s, "new".to_string(), vec![key("0000")]), Err(XidError::FieldOutOfRange)));
+        assert!(add_tag(&s, "t0".to_string(), vec![key("0000")]).is_ok());
+    }
+
+    #[test]
+    fn collection_add_and_remove() {
+        let s = content_state(3);
+        add_collection_items(&s, "c", vec![key("0002"), key("0000")], NOW + 1).unwrap();
+        add_collection_items(&s, "c", vec![key("0000"), key("0001"), key("0001")], NOW + 2).unwrap();
+        assert_eq!(collection_items(&s), vec!["0002", "0000", "0001"]);
+        assert_eq!(s.collections.borrow()["c"].update_time, NOW + 2);
+        assert!(matches!(add_collection_items(&s, "x", vec![key("0000")], NOW), Err(XidError::CollectionNotExist)));
+        assert!(matches!(add_collection_items(&s, "c", vec![key("9999")], NOW), Err(XidError::UuidNotExist)));
+
+        remove_collection_items(&s, "c", &[key("0000"), key("9999")], NOW + 3).unwrap();
+        assert_eq!(collection_items(&s), vec!["0002", "0001"]);
+        assert!(matches!(remove_collection_items(&s, "x", &[], NOW), Err(XidError::CollectionNotExist)));
+    }
+
+    #[test]
+    fn collection_is_capped() {
+        let s = content_state(MAX_COLLECTION_ITEMS + 1);
+        let all : Vec<ContentUuid> = (0..MAX_COLLECTION_ITEMS + 1).map(|i| key(&format!("{:04}", i))).collect();
+        assert!(matches!(add_collection_items(&s, "c", all.clone(), NOW), Err(XidError::FieldOutOfRange)));
+        assert!(collection_items(&s).is_empty());
+        add_collection_items(&s, "c", all[..MAX_COLLECTION_ITEMS].to_vec(), NOW).unwrap();
+        // 已在合集中的不计入
+        assert!(add_collection_items(&s, "c", all[..1].to_vec(), NOW).is_ok());
+        assert!(matches!(add_collection_items(&s, "c", all[MAX_COLLECTION_ITEMS..].to_vec(), NOW),
+                         Err(XidError::FieldOutOfRange)));
+    }
+
+    #[test]
+    fn restore_returns_tags_and_position() {
+        let s = content_state(3);
+        add_tag(&s, "only".to_string(), vec![key("0001")]).unwrap();
+        add_tag(&s, "shared".to_string(), vec![key("0001"), key("0002")]).unwrap();
+        add_collection_items(&s, "c", vec![key("0000"), key("0001"), key("0002")], NOW).unwrap();
+
+        trash_item(&s, key("0001"), NOW).unwrap();
+        assert!(!s.tags.borrow().contains_key("only"));
+        assert_eq!(collection_items(&s), vec!["0000", "0002"]);
+        assert!(matches!(trash_item(&s, key("0001"), NOW), Err(XidError::UuidNotExist)));
+
+        restore_item(&s, key("0001")).unwrap();
+        assert!(s.tags.borrow()["only"].contains(&key("0001")));
+        assert!(s.tags.borrow()["shared"].contains(&key("0001")));
+        assert_eq!(collection_items(&s), vec!["0000", "0001", "0002"]);
+        assert!(s.stores.borrow()[&ContentType::OffChain].contains_key("0001"));
+        assert!(s.trash.borrow().is_empty());
+        assert!(matches!(restore_item(&s, key("0001")), Err(XidError::UuidNotExist)));
+    }
+
+    #[test]
+    fn restore_respects_caps() {
+        let s = content_state(MAX_COLLECTION_ITEMS + 1);
+        let all : Vec<ContentUuid> = (0..MAX_COLLECTION_ITEMS + 1).map(|i| key(&format!("{:04}", i))).collect();
+        add_tag(&s, "gone".to_string(), vec![all[0].clone()]).unwrap();
+        add_collection_items(&s, "c", all[..MAX_COLLECTION_ITEMS].to_vec(), NOW).unwrap();
+        trash_item(&s, all[0].clone(), NOW).unwrap();
+
+        // 删除期间合集被填满, 标签数达到上限
+        add_collection_items(&s, "c", vec![all[MAX_COLLECTION_ITEMS].clone()], NOW).unwrap();
+        for i in 0..MAX_TAGS {
+            add_tag(&s, format!("t{}", i), vec![all[1].clone()]).unwrap();
+        }
+        restore_item(&s, all[0].clone()).unwrap();
+        assert_eq!(s.collections.borrow()["c"].items.len(), MAX_COLLECTION_ITEMS);
+        assert!(!s.collections.borrow()["c"].items.contains(&all[0]));
+        assert_eq!(s.tags.borrow().len(), MAX_TAGS);
+        assert!(!s.tags.borrow().contains_key("gone"));
+        assert!(s.stores.borrow()[&ContentType::OffChain].contains_key("0000"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use ic_certified_map::Hash;
+use crate::types::{MediaAsset, UploadSession, XidError};
+
+// 单块上限, 低于ingress消息与http响应的大小限制
+pub const MAX_CHUNK_SIZE : usize = 1_900_000;
+pub const MAX_MEDIA_SIZE : u64 = 64 * 1024 * 1024;
+pub const MAX_UPLOAD_SESSIONS : usize = 8;
+pub const MAX_KEY_LEN : usize = 128;
+// 媒体保存在堆内存中并在升级时整体序列化, 总量与数量均需受限
+pub const MAX_MEDIA_TOTAL : u64 = 256 * 1024 * 1024;
+pub const MAX_MEDIA_COUNT : usize = 256;
+// 未提交的上传会话在此时间后清理
+pub const UPLOAD_SESSION_TTL : u64 = 60 * 60 * 1_000_000_000;
+
+// 媒体key作为url路径的一部分, 仅允许字母数字及 . _ - /
+pub fn check_key(key : &str) -> Result<(), XidError> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && !key.starts_with('/')
+        && !key.split('/').any(|part| part.is_empty() || part == "." || part == "..")
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'));
+    if !valid {
+        return Err(XidError::InvalidName);
+    };
+    Ok(())
+}
+
+// 已提交的媒体与上传中的会话一并计入总量, 同名key在提交前不释放
+pub fn total_size(media : &BTreeMap<String, MediaAsset>, sessions : &BTreeMap<u64, UploadSession>) -> u64 {
+    media.values().map(|asset| asset.length)
+        .chain(sessions.values().map(|session| session.size))
+        .fold(0, u64::saturating_add)
+}
+
+pub fn check_total(total : u64, added : u64) -> Result<(), XidError> {
+    if total.saturating_add(added) > MAX_MEDIA_TOTAL {
+        return Err(XidError::MediaQuotaExceeded);
+    };
+    Ok(())
+}
+
+// 覆盖已有key不增加数量
+pub fn check_count(media : &BTreeMap<String, MediaAsset>, key : &str) -> Result<(), XidError> {
+    if !media.contains_key(key) && media.len() >= MAX_MEDIA_COUNT {
+        return Err(XidError::MediaQuotaExceeded);
+    };
+    Ok(())
+}
+
+// 块须从0开始连续, 拼接后的sha256须与客户端提供的一致
+pub fn check(session : &UploadSession, sha256 : &[u8]) -> Result<ByteBuf, XidError> {
+    if session.chunks.is_empty()
+        || session.chunks.keys().enumerate().any(|(i, index)| i as u64 != *index) {
+        return Err(XidError::FieldOutOfRange);
+    };
+    let mut hasher = Sha256::new();
+    for chunk in session.chunks.values() {
+        hasher.update(chunk);
+    }
+    let digest = hasher.finalize();
+    if digest[..] != *sha256 {
+        return Err(XidError::HashMismatch);
+    };
+    Ok(ByteBuf::from(digest.to_vec()))
+}
+
+pub fn into_asset(session : UploadSession, sha256 : ByteBuf, now : u64) -> MediaAsset {
+    MediaAsset {
+        key: session.key,
+        content_type: session.content_type,
+        chunks: session.chunks.into_values().collect(),
+        length: session.size,
+        sha256,
+        upload_time: now,
+    }
+}
+
+pub fn media_path(key : &str) -> String {
+    format!("/media/{}", key)
+}
+
+// sha256在提交时已计算, 直接作为证书树的叶子
+pub fn certified_hash(asset : &MediaAsset) -> Hash {
+    asset.sha256.as_slice().try_into().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(key : &str, chunks : &[(u64, &[u8])]) -> UploadSession {
+        UploadSession {
+            key: key.to_string(),
+            content_type: "image/png".to_string(),
+            chunks: chunks.iter().map(|(i, c)| (*i, ByteBuf::from(c.to_vec()))).collect(),
+            size: chunks.iter().map(|(_, c)| c.len() as u64).sum(),
+            expire_time: 0,
+        }
+    }
+
+    fn asset(key : &str, length : u64) -> MediaAsset {
+        MediaAsset {
+            key: key.to_string(),
+            content_type: "image/png".to_string(),
+            chunks: vec![],
+            length,
+            sha256: ByteBuf::new(),
+            upload_time: 0,
+        }
+    }
+
+    #[test]
+    fn keys_are_url_safe() {
+        assert!(check_key("avatar.png").is_ok());
+        assert!(check_key("img/2023/a_b-c.jpg").is_ok());
+        for key in ["", "/a", "a//b", "a/../b", "./a", "a/", "a b", "a?b", &"a".repeat(MAX_KEY_LEN + 1)] {
+            assert!(check_key(key).is_err(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn chunks_must_be_contiguous_and_match_hash() {
+        let digest = Sha256::digest(b"hello world").to_vec();
+        let ok = session("a", &[(0, b"hello "), (1, b"world")]);
+        assert_eq!(check(&ok, &digest).unwrap().to_vec(), digest);
+        assert!(matches!(check(&ok, &[0; 32]), Err(XidError::HashMismatch)));
+        let gap = session("a", &[(0, b"hello "), (2, b"world")]);
+        assert!(matches!(check(&gap, &digest), Err(XidError::FieldOutOfRange)));
+        assert!(matches!(check(&session("a", &[]), &digest), Err(XidError::FieldOutOfRange)));
+    }
+
+    #[test]
+    fn total_counts_media_and_sessions() {
+        let mut media = BTreeMap::new();
+        media.insert("a".to_string(), asset("a", MAX_MEDIA_TOTAL - 10));
+        let mut sessions = BTreeMap::new();
+        sessions.insert(1, session("b", &[(0, &[0; 6])]));
+        let total = total_size(&media, &sessions);
+        assert_eq!(total, MAX_MEDIA_TOTAL - 4);
+        assert!(check_total(total, 4).is_ok());
+        assert!(matches!(check_total(total, 5), Err(XidError::MediaQuotaExceeded)));
+        assert!(check_total(u64::MAX, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn count_ignores_overwrites() {
+        let media : BTreeMap<_, _> = (0..MAX_MEDIA_COUNT)
+            .map(|i| (i.to_string(), asset(&i.to_string(), 1)))
+            .collect();
+        assert!(check_count(&media, "0").is_ok());
+        assert!(matches!(check_count(&media, "new"), Err(XidError::MediaQuotaExceeded)));
+    }
+}
@@ -0,0 +1,287 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use askama::Template;
+use candid::Principal;
+use serde_bytes::ByteBuf;
+use crate::certify;
+use crate::disclosure::{public_ids, public_main_id};
+use crate::rc_bytes::RcBytes;
+use crate::timestamp;
+use crate::types::{Contents, State, ID};
+
+pub const PROFILE_PATHS : [&str; 2] = ["/", "/index.html"];
+pub const HTML_TYPE : &str = "text/html; charset=utf-8";
+pub const PUBLIC_DOMAIN : &str = "icp0.io";
+pub const RECENT_ITEMS : usize = 10;
+pub const MAX_TEXT_LEN : usize = 280;
+pub const MAX_DESCRIPTION_LEN : usize = 160;
+// 数据变化后延迟重新渲染, 期间的多次修改合并为一次
+pub const RENDER_DELAY : u64 = 5 * 1_000_000_000;
+
+// 预渲染的文档, ETag为内容sha256, 内容变化时更新Last-Modified
+#[derive(Clone, Debug)]
+pub(crate) struct Document {
+    pub content_type : String,
+    pub body : RcBytes,
+    pub etag : String,
+    pub last_modified : u64,
+}
+
+impl Document {
+    // previous为同一路径的旧文档, 内容未变时保留其Last-Modified
+    pub fn new(content_type : &str, body : Vec<u8>, previous : Option<&Document>, now : u64) -> Self {
+        let hash = certify::hash(&body);
+        let etag = format!("\"{}\"", hash.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let last_modified = match previous {
+            Some(doc) if doc.etag == etag => doc.last_modified,
+            _ => now,
+        };
+        Document {
+            content_type: content_type.to_string(),
+            body: RcBytes::from(ByteBuf::from(body)),
+            etag,
+            last_modified,
+        }
+    }
+
+    // 条件请求: If-None-Match优先, 其次按秒比较If-Modified-Since
+    pub fn not_modified(&self, headers : &[(String, String)]) -> bool {
+        let header = |name : &str| headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str());
+        if let Some(tags) = header("If-None-Match") {
+            return tags.split(',')
+                .map(|t| t.trim().trim_start_matches("W/"))
+                .any(|t| t == "*" || t == self.etag);
+        };
+        match header("If-Modified-Since").and_then(timestamp::parse_http_date) {
+            Some(since) => self.last_modified / 1_000_000_000 <= since / 1_000_000_000,
+            None => false,
+        }
+    }
+}
+
+thread_local! {
+    // 预渲染并认证的文档: 路径 -> 文档, 不持久化, post_upgrade时重建
+    static PAGES : RefCell<BTreeMap<String, Document>> = const { RefCell::new(BTreeMap::new()) };
+    // 数据变化后置位, 由定时器重新渲染
+    static DIRTY : Cell<bool> = const { Cell::new(true) };
+}
+
+// 首次置位时设定RENDER_DELAY后的渲染, 已置位时不再推迟
+pub fn invalidate() {
+    if !DIRTY.with(|d| d.replace(true)) {
+        crate::timer::schedule(ic_cdk::api::time().saturating_add(RENDER_DELAY));
+    };
+}
+
+pub fn take_dirty() -> bool {
+    DIRTY.with(|d| d.replace(false))
+}
+
+pub(crate) fn get(path : &str) -> Option<Document> {
+    PAGES.with(|p| p.borrow().get(path).cloned())
+}
+
+// 仅能在update/定时器/post_upgrade中调用
+pub fn set(path : &str, content_type : &str, body : Vec<u8>, now : u64) {
+    certify::put(path, certify::hash(&body));
+    PAGES.with(|p| {
+        let mut pages = p.borrow_mut();
+        let doc = Document::new(content_type, body, pages.get(path), now);
+        pages.insert(path.to_string(), doc);
+    })
+}
+
+// 替换prefix下的全部文档, 不再存在的路径从证书树中移除; 仅能在update/定时器/post_upgrade中调用
+pub fn replace_prefix(prefix : &str, content_type : &str, documents : BTreeMap<String, Vec<u8>>, now : u64) {
+    let stale : Vec<String> = PAGES.with(|p| p.borrow().keys()
+        .filter(|path| path.starts_with(prefix) && !documents.contains_key(*path))
+        .cloned()
+        .collect());
+    for path in stale {
+        certify::remove(&path);
+        PAGES.with(|p| p.borrow_mut().remove(&path));
+    }
+    for (path, body) in documents {
+        set(&path, content_type, body, now);
+    }
+}
+
+struct RecentItem {
+    kind : String,
+    text : String,
+    url : Option<String>,
+    date : String,
+}
+
+#[derive(Template)]
+#[template(path = "profile.html")]
+struct ProfilePage<'a> {
+    name : &'a str,
+    title : String,
+    description : String,
+    url : String,
+    image : Option<String>,
+    main_id : Option<&'a ID>,
+    ids : Vec<&'a ID>,
+    items : Vec<RecentItem>,
+}
+
+// 公开页面的绝对地址前缀 https://<canister id>.icp0.io
+pub fn public_base(canister : Principal) -> String {
+    format!("https://{}.{}", canister, PUBLIC_DOMAIN)
+}
+
+// 公开主页, base为public_base
+pub fn render_profile(s : &State, base : &str) -> Vec<u8> {
+    let name = s.name.borrow();
+    let main_id = public_main_id(s);
+    let ids = public_ids(s);
+    let stores = s.stores.borrow();
+    let display_name = if name.is_empty() { "xid" } else { name.as_str() };
+    let main_id = main_id.as_ref();
+    let description = match main_id {
+        Some(id) => format!("{} · {} identities bound on xid", id.identity, ids.len()),
+        None => format!("{} identities bound on xid", ids.len()),
+    };
+    let items = s.timeline.borrow().iter().rev()
+        .filter_map(|(time, content_type, uuid)| {
+            let item = stores.get(content_type)?.get(uuid)?;
+            let (text, url) = match &item.content {
+                Contents::TwitterContent(t) => (t.text_content.clone(), t.url.clone()),
+                Contents::OffChainContent(o) => (o.text_content.clone(), o.url.clone()),
+                Contents::Custom(c) => (c.fields.iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .collect::<Vec<String>>()
+                    .join("\n"), String::new()),
+            };
+            Some(RecentItem {
+                kind: content_type.name().to_string(),
+                text: truncate(&text, MAX_TEXT_LEN),
+                url: safe_url(&url, base),
+                date: timestamp::format_date(*time),
+            })
+        })
+        .take(RECENT_ITEMS)
+        .collect();
+    let page = ProfilePage {
+        name: display_name,
+        title: format!("{} · xid", display_name),
+        description: truncate(&description, MAX_DESCRIPTION_LEN),
+        url: format!("{}/", base),
+        image: avatar_url(s, base),
+        main_id,
+        ids: ids.iter().collect(),
+        items,
+    };
+    page.render().unwrap_or_default().into_bytes()
+}
+
+// 优先使用上传的头像, 其次为avatar_url; og:image需要绝对地址
+pub fn avatar_url(s : &State, base : &str) -> Option<String> {
+    if !s.avatar.borrow().image_data.is_empty() {
+        return Some(format!("{}{}", base, certify::AVATAR_PATH));
+    };
+    safe_url(&s.avatar_url.borrow(), base)
+}
+
+// 仅输出http(s)链接与站内路径, 避免javascript:等伪协议
+pub fn safe_url(url : &str, base : &str) -> Option<String> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Some(url.to_string())
+    } else if url.starts_with('/') && !url.starts_with("//") {
+        Some(format!("{}{}", base, url))
+    } else {
+        None
+    }
+}
+
+pub fn truncate(text : &str, max : usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    };
+    let mut out : String = text.chars().take(max).collect();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ContentType, CustomContent, Storage, TwitterContent};
+
+    const BASE : &str = "https://sbcxh-pyaaa-aaaal-qbolq-cai.icp0.io";
+    const SCRIPT : &str = "<script>alert(1)</script>";
+    const QUOTE : &str = "\" onmouseover=\"alert(1)";
+
+    fn item(content_type : ContentType, uuid : &str, content : Contents) -> Storage {
+        Storage {
+            owner: String::new(),
+            uuid: uuid.to_string(),
+            content_type,
+            content,
+            d_platform: String::new(),
+            is_minted: false,
+            mint_time: None,
+            upload_time: 1_700_000_000_000_000_000,
+            edit_time: None,
+        }
+    }
+
+    fn state() -> State {
+        let s = State::default();
+        *s.name.borrow_mut() = format!("{}{}", SCRIPT, QUOTE);
+        *s.avatar_url.borrow_mut() = format!("https://example.com/a.png{}", QUOTE);
+        let bound = ID { platform: format!("x{}", QUOTE), identity: SCRIPT.to_string(), bind_time: 1 };
+        s.ids.borrow_mut().insert(bound.clone());
+        *s.main_id.borrow_mut() = bound;
+        let items = [
+            item(ContentType::Twitter, "t1", Contents::TwitterContent(TwitterContent {
+                url: format!("https://twitter.com/alice{}", QUOTE),
+                text_content: SCRIPT.to_string(),
+                text_url: String::new(),
+                image_urls: vec![],
+                video_url: String::new(),
+                post_time: 1_600_000_000_000_000_000,
+            })),
+            item(ContentType::Custom(format!("bio{}", QUOTE)), "c1", Contents::Custom(CustomContent {
+                content_type: "bio".to_string(),
+                fields: vec![(format!("about{}", QUOTE), SCRIPT.to_string())],
+            })),
+        ];
+        for item in items {
+            s.timeline.borrow_mut().insert(item.timeline_key());
+            s.stores.borrow_mut().entry(item.content_type.clone()).or_default().insert(item.uuid.clone(), item);
+        }
+        s
+    }
+
+    #[test]
+    fn profile_escapes_user_fields() {
+        let s = state();
+        let page = String::from_utf8(render_profile(&s, BASE)).unwrap();
+        assert!(!page.contains("<script"), "{}", page);
+        assert!(!page.contains("\" onmouseover"), "{}", page);
+        assert!(!page.contains("onmouseover=\""), "{}", page);
+        // 名称, 身份, 推文与自定义内容均按HTML转义后输出
+        assert!(page.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;&quot; onmouseover=&quot;alert(1)</h1>"));
+        assert!(page.contains("<li><span>x&quot; onmouseover=&quot;alert(1)</span>&lt;script&gt;"));
+        assert!(page.contains("<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>"));
+        assert!(page.contains("<p>about&quot; onmouseover=&quot;alert(1): &lt;script&gt;"));
+        assert!(page.contains("bio&quot; onmouseover=&quot;alert(1) ·"));
+        assert!(page.contains("href=\"https://twitter.com/alice&quot; onmouseover=&quot;alert(1)\""));
+        assert!(page.contains("content=\"https://example.com/a.png&quot; onmouseover=&quot;alert(1)\""));
+    }
+
+    #[test]
+    fn unsafe_urls_are_dropped() {
+        let s = state();
+        *s.avatar_url.borrow_mut() = "javascript:alert(1)".to_string();
+        let page = String::from_utf8(render_profile(&s, BASE)).unwrap();
+        assert!(!page.contains("javascript:"));
+        assert!(!page.contains("og:image"));
+        assert_eq!(safe_url("//evil.example/x", BASE), None);
+        assert_eq!(safe_url("/media/a", BASE), Some(format!("{}/media/a", BASE)));
+    }
+}
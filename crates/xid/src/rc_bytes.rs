@@ -20,7 +20,7 @@ impl CandidType for RcBytes {
         where
             S: Serializer,
     {
-        serializer.serialize_blob((&*self.0).as_ref())
+        serializer.serialize_blob((*self.0).as_ref())
     }
 }
 
@@ -41,13 +41,13 @@ impl From<ByteBuf> for RcBytes {
 
 impl AsRef<[u8]> for RcBytes {
     fn as_ref(&self) -> &[u8] {
-        (&*self.0).as_ref()
+        (*self.0).as_ref()
     }
 }
 
 impl Deref for RcBytes {
     type Target = [u8];
     fn deref(&self) -> &[u8] {
-        (&*self.0).as_ref()
+        (*self.0).as_ref()
     }
 }
\ No newline at end of file
@@ -0,0 +1,153 @@
+use candid::Principal;
+use crate::timestamp;
+use crate::types::{RecoveryConfig, RecoveryRequest, XidError};
+
+pub const MAX_RECOVERY_WINDOW : u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+pub const MAX_RECOVERY_DELAY : u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+pub fn check_config(config : &RecoveryConfig) -> Result<(), XidError> {
+    if config.threshold == 0
+        || config.threshold as usize > config.guardians.len()
+        || config.window == 0
+        || config.window > MAX_RECOVERY_WINDOW
+        || config.delay > MAX_RECOVERY_DELAY {
+        return Err(XidError::FieldOutOfRange);
+    };
+    Ok(())
+}
+
+// 未达到阈值且超出审批窗口的请求可被新请求替换
+pub fn is_expired(config : &RecoveryConfig, r : &RecoveryRequest, now : u64) -> bool {
+    r.approved_time.is_none() && r.start_time.saturating_add(config.window) < now
+}
+
+pub fn is_ready(config : &RecoveryConfig, r : &RecoveryRequest, now : u64) -> bool {
+    r.approved_time.is_some_and(|t| t.saturating_add(config.delay) <= now)
+}
+
+// 签名审批的action: "recover:<canister id>:<new owner principal>:<nonce>"
+pub fn action(canister : &Principal, new_owner : &Principal, nonce : u64) -> String {
+    format!("recover:{}:{}:{}", canister.to_text(), new_owner.to_text(), nonce)
+}
+
+// 返回new owner与nonce, canister不符时视为无效
+pub fn parse_action(action : &str, canister : &Principal) -> Option<(Principal, u64)> {
+    let mut parts = action.strip_prefix("recover:")?.split(':');
+    let (target, new_owner, nonce) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || target != canister.to_text() {
+        return None;
+    };
+    Some((Principal::from_text(new_owner).ok()?, nonce.parse().ok()?))
+}
+
+// nonce须为进行中请求的start_time; 无进行中的请求时为0, 且签名须在审批窗口内创建
+pub fn check_nonce(
+    config : &RecoveryConfig,
+    current : Option<&RecoveryRequest>,
+    nonce : u64,
+    created_at : u64,
+    now : u64,
+) -> Result<(), XidError> {
+    match current.filter(|r| !is_expired(config, r, now)) {
+        Some(r) if r.start_time == nonce => Ok(()),
+        Some(_) => Err(XidError::FieldOutOfRange),
+        None => {
+            timestamp::check(created_at, now)?;
+            if nonce != 0 || now.saturating_sub(created_at) > config.window {
+                return Err(XidError::FieldOutOfRange);
+            };
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Guardian;
+
+    const SECOND : u64 = 1_000_000_000;
+    const NOW : u64 = 1_700_000_000 * SECOND;
+
+    fn config(window : u64, delay : u64) -> RecoveryConfig {
+        RecoveryConfig {
+            guardians: vec![Guardian::Principal(Principal::anonymous())],
+            threshold: 1,
+            window,
+            delay,
+        }
+    }
+
+    fn request(start_time : u64, approved_time : Option<u64>) -> RecoveryRequest {
+        RecoveryRequest {
+            new_owner: Principal::anonymous().to_text(),
+            start_time,
+            approvals: vec![],
+            approved_time,
+        }
+    }
+
+    #[test]
+    fn config_bounds() {
+        assert!(check_config(&config(SECOND, 0)).is_ok());
+        assert!(check_config(&config(MAX_RECOVERY_WINDOW, MAX_RECOVERY_DELAY)).is_ok());
+        assert!(check_config(&config(0, 0)).is_err());
+        assert!(check_config(&config(MAX_RECOVERY_WINDOW + 1, 0)).is_err());
+        assert!(check_config(&config(SECOND, MAX_RECOVERY_DELAY + 1)).is_err());
+        assert!(check_config(&config(u64::MAX, u64::MAX)).is_err());
+        let mut c = config(SECOND, 0);
+        c.threshold = 2;
+        assert!(check_config(&c).is_err());
+    }
+
+    #[test]
+    fn window_and_delay_timing() {
+        let c = config(10, 5);
+        assert!(!is_expired(&c, &request(NOW, None), NOW + 10));
+        assert!(is_expired(&c, &request(NOW, None), NOW + 11));
+        assert!(!is_expired(&c, &request(NOW, Some(NOW + 1)), u64::MAX));
+        assert!(!is_ready(&c, &request(NOW, None), u64::MAX));
+        assert!(!is_ready(&c, &request(NOW, Some(NOW)), NOW + 4));
+        assert!(is_ready(&c, &request(NOW, Some(NOW)), NOW + 5));
+    }
+
+    #[test]
+    fn timing_saturates() {
+        let c = RecoveryConfig { window: u64::MAX, delay: u64::MAX, ..config(0, 0) };
+        assert!(!is_expired(&c, &request(u64::MAX - 1, None), u64::MAX));
+        assert!(!is_ready(&c, &request(0, Some(u64::MAX - 1)), u64::MAX - 1));
+        assert!(is_ready(&c, &request(0, Some(1)), u64::MAX));
+    }
+
+    #[test]
+    fn action_round_trip() {
+        let canister = Principal::from_text("sbcxh-pyaaa-aaaal-qbolq-cai").unwrap();
+        let owner = Principal::anonymous();
+        let a = action(&canister, &owner, NOW);
+        assert_eq!(parse_action(&a, &canister), Some((owner, NOW)));
+        assert_eq!(parse_action(&a, &Principal::management_canister()), None);
+        assert_eq!(parse_action(&format!("recover:{}", owner), &canister), None);
+        assert_eq!(parse_action(&format!("{}:1", a), &canister), None);
+        assert_eq!(parse_action(&format!("recover:{}:{}:x", canister, owner), &canister), None);
+    }
+
+    #[test]
+    fn nonce_binds_to_current_request() {
+        let c = config(100 * SECOND, 0);
+        let pending = request(NOW - SECOND, None);
+        assert!(check_nonce(&c, Some(&pending), NOW - SECOND, 0, NOW).is_ok());
+        assert!(check_nonce(&c, Some(&pending), 0, NOW, NOW).is_err());
+        assert!(check_nonce(&c, Some(&pending), NOW, NOW, NOW).is_err());
+
+        // 新请求只接受窗口内创建的签名
+        assert!(check_nonce(&c, None, 0, NOW - SECOND, NOW).is_ok());
+        assert!(check_nonce(&c, None, 0, NOW - 101 * SECOND, NOW).is_err());
+        assert!(check_nonce(&c, None, NOW - SECOND, NOW, NOW).is_err());
+        assert!(check_nonce(&c, None, 0, 0, NOW).is_err());
+
+        // 过期的请求不再接受其nonce
+        let expired = request(NOW - 200 * SECOND, None);
+        assert!(check_nonce(&c, Some(&expired), NOW - 200 * SECOND, NOW, NOW).is_err());
+        assert!(check_nonce(&c, Some(&expired), 0, NOW, NOW).is_ok());
+    }
+}
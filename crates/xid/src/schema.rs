@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use crate::types::{ContentSchema, CustomContent, FieldKind, XidError};
+
+pub const BUILTIN_TYPES : [&str; 2] = ["Twitter", "OffChain"];
+pub const MAX_NAME_LEN : usize = 64;
+
+// 校验owner注册的schema定义
+pub fn check_schema(schema : &ContentSchema) -> Result<(), XidError> {
+    if !is_valid_name(&schema.name) || BUILTIN_TYPES.contains(&schema.name.as_str()) {
+        return Err(XidError::InvalidContent(format!("invalid type name: {}", schema.name)));
+    };
+    if schema.fields.is_empty() {
+        return Err(XidError::InvalidContent("schema has no fields".to_string()));
+    };
+    let mut names = BTreeSet::new();
+    for f in schema.fields.iter() {
+        if !is_valid_name(&f.name) || !names.insert(f.name.as_str()) {
+            return Err(XidError::InvalidContent(format!("invalid field name: {}", f.name)));
+        };
+    }
+    Ok(())
+}
+
+// 按schema校验自定义内容: 必填字段, 未声明字段, 类型与长度
+pub fn validate(schema : &ContentSchema, content : &CustomContent) -> Result<(), XidError> {
+    let mut seen = BTreeSet::new();
+    for (name, value) in content.fields.iter() {
+        let spec = match schema.fields.iter().find(|f| f.name == *name) {
+            Some(f) => f,
+            None => return Err(XidError::InvalidContent(format!("unknown field: {}", name))),
+        };
+        if !seen.insert(name.as_str()) {
+            return Err(XidError::InvalidContent(format!("duplicate field: {}", name)));
+        };
+        if spec.max_len.is_some_and(|m| value.chars().count() as u64 > m) {
+            return Err(XidError::InvalidContent(format!("field too long: {}", name)));
+        };
+        let valid = match spec.kind {
+            FieldKind::Text => true,
+            FieldKind::Url => is_url(value),
+            FieldKind::Number => value.parse::<f64>().is_ok_and(|n| n.is_finite()),
+            FieldKind::Timestamp => value.parse::<u64>().is_ok(),
+        };
+        if !valid {
+            return Err(XidError::InvalidContent(format!("invalid value for field: {}", name)));
+        };
+    }
+    for f in schema.fields.iter().filter(|f| f.required) {
+        if !seen.contains(f.name.as_str()) {
+            return Err(XidError::InvalidContent(format!("missing field: {}", f.name)));
+        };
+    }
+    Ok(())
+}
+
+fn is_valid_name(name : &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// 含本canister的媒体路径 /media/<key>
+fn is_url(value : &str) -> bool {
+    ["https://", "http://", "ipfs://", "ar://", "/media/"].iter()
+        .any(|scheme| value.len() > scheme.len() && value.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FieldSpec;
+
+    fn field(name : &str, kind : FieldKind, required : bool, max_len : Option<u64>) -> FieldSpec {
+        FieldSpec { name: name.to_string(), kind, required, max_len }
+    }
+
+    fn schema() -> ContentSchema {
+        ContentSchema {
+            name: "Article".to_string(),
+            fields: vec![
+                field("title", FieldKind::Text, true, Some(5)),
+                field("link", FieldKind::Url, false, None),
+                field("score", FieldKind::Number, false, None),
+                field("published", FieldKind::Timestamp, false, None),
+            ],
+        }
+    }
+
+    fn content(fields : &[(&str, &str)]) -> CustomContent {
+        CustomContent {
+            content_type: "Article".to_string(),
+            fields: fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn schema_definitions() {
+        assert!(check_schema(&schema()).is_ok());
+        for name in ["", "Twitter", "OffChain", "has space", &"a".repeat(MAX_NAME_LEN + 1)] {
+            assert!(check_schema(&ContentSchema { name: name.to_string(), ..schema() }).is_err(), "{}", name);
+        }
+        assert!(check_schema(&ContentSchema { fields: vec![], ..schema() }).is_err());
+        let mut duplicate = schema();
+        duplicate.fields.push(field("title", FieldKind::Text, false, None));
+        assert!(check_schema(&duplicate).is_err());
+        let mut bad_field = schema();
+        bad_field.fields.push(field("a.b", FieldKind::Text, false, None));
+        assert!(check_schema(&bad_field).is_err());
+    }
+
+    #[test]
+    fn required_unknown_and_duplicate_fields() {
+        let s = schema();
+        assert!(validate(&s, &content(&[("title", "hi")])).is_ok());
+        assert!(validate(&s, &content(&[("link", "https://a.b")])).is_err());
+        assert!(validate(&s, &content(&[("title", "hi"), ("extra", "x")])).is_err());
+        assert!(validate(&s, &content(&[("title", "hi"), ("title", "again")])).is_err());
+    }
+
+    #[test]
+    fn field_kinds_and_length() {
+        let s = schema();
+        let check = |name : &str, value : &str| validate(&s, &content(&[("title", "t"), (name, value)])).is_ok();
+        assert!(validate(&s, &content(&[("title", "你好世界啊")])).is_ok());
+        assert!(validate(&s, &content(&[("title", "123456")])).is_err());
+        assert!(check("link", "https://example.com"));
+        assert!(check("link", "/media/a.png"));
+        assert!(check("link", "ipfs://cid"));
+        assert!(!check("link", "https://"));
+        assert!(!check("link", "ftp://example.com"));
+        assert!(check("score", "-1.5e3"));
+        assert!(!check("score", "NaN"));
+        assert!(!check("score", "inf"));
+        assert!(!check("score", "ten"));
+        // 自定义时间字段可为任意纳秒时间戳, 包括很早的日期
+        assert!(check("published", "0"));
+        assert!(check("published", "946684800000000000"));
+        assert!(check("published", &u64::MAX.to_string()));
+        assert!(!check("published", "-1"));
+        assert!(!check("published", "18446744073709551616"));
+        assert!(!check("published", "2023-11-14"));
+    }
+}
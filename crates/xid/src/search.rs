@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::{Contents, ContentUuid, Storage};
+
+pub const MAX_QUERY_LEN : usize = 256;
+pub const MAX_QUERY_TERMS : usize = 16;
+pub const MAX_TERM_LEN : usize = 64;
+
+// 倒排索引: 词 -> 内容, 不持久化, post_upgrade时重建
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct SearchIndex {
+    terms : BTreeMap<String, BTreeSet<ContentUuid>>,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, item : &Storage) {
+        let key = item.key();
+        for term in item_terms(&item.content) {
+            self.terms.entry(term).or_default().insert(key.clone());
+        }
+    }
+
+    pub fn remove(&mut self, item : &Storage) {
+        let key = item.key();
+        for term in item_terms(&item.content) {
+            if let Some(docs) = self.terms.get_mut(&term) {
+                docs.remove(&key);
+                if docs.is_empty() {
+                    self.terms.remove(&term);
+                };
+            };
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.terms.clear();
+    }
+
+    // 每个查询词按前缀匹配, 多个词取交集
+    pub fn search(&self, terms : &[String]) -> BTreeSet<ContentUuid> {
+        let mut result : Option<BTreeSet<ContentUuid>> = None;
+        for term in terms {
+            let mut docs = BTreeSet::new();
+            for (_, ids) in self.terms
+                .range::<String, _>((Bound::Included(term), Bound::Unbounded))
+                .take_while(|(t, _)| t.starts_with(term.as_str())) {
+                docs.extend(ids.iter().cloned());
+            }
+            result = Some(match result {
+                Some(prev) => prev.intersection(&docs).cloned().collect(),
+                None => docs,
+            });
+            if result.as_ref().is_some_and(|r| r.is_empty()) {
+                break;
+            };
+        }
+        result.unwrap_or_default()
+    }
+}
+
+fn item_terms(content : &Contents) -> BTreeSet<String> {
+    let mut terms = BTreeSet::new();
+    let texts : Vec<&str> = match content {
+        Contents::TwitterContent(t) => {
+            let mut v = vec![t.text_content.as_str(), t.url.as_str(), t.text_url.as_str(), t.video_url.as_str()];
+            v.extend(t.image_urls.iter().map(|u| u.as_str()));
+            v
+        },
+        Contents::OffChainContent(o) => vec![o.text_content.as_str(), o.url.as_str()],
+        Contents::Custom(c) => c.fields.iter().map(|(_, v)| v.as_str()).collect(),
+    };
+    for text in texts {
+        terms.extend(tokenize(text, false));
+    }
+    terms
+}
+
+// 查询串分词, 过长或词数过多时返回None
+pub fn query_terms(query : &str) -> Option<Vec<String>> {
+    if query.chars().count() > MAX_QUERY_LEN {
+        return None;
+    };
+    let terms : BTreeSet<String> = tokenize(query, true).into_iter().collect();
+    if terms.is_empty() || terms.len() > MAX_QUERY_TERMS {
+        return None;
+    };
+    Some(terms.into_iter().collect())
+}
+
+// 字母数字按词切分并转小写; 中日韩文字无空格分隔, 建索引时取单字与相邻双字,
+// 查询时取双字(单字则取单字), 配合前缀匹配即可命中
+pub fn tokenize(text : &str, for_query : bool) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut cjk : Vec<char> = Vec::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk, &mut tokens, for_query);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_word(&mut word, &mut tokens);
+            flush_cjk(&mut cjk, &mut tokens, for_query);
+        }
+    }
+    flush_word(&mut word, &mut tokens);
+    flush_cjk(&mut cjk, &mut tokens, for_query);
+    tokens
+}
+
+fn flush_word(word : &mut String, tokens : &mut Vec<String>) {
+    if !word.is_empty() {
+        tokens.push(word.chars().take(MAX_TERM_LEN).collect());
+        word.clear();
+    };
+}
+
+fn flush_cjk(run : &mut Vec<char>, tokens : &mut Vec<String>, for_query : bool) {
+    if !for_query || run.len() == 1 {
+        tokens.extend(run.iter().map(|c| c.to_string()));
+    };
+    tokens.extend(run.windows(2).map(|w| w.iter().collect::<String>()));
+    run.clear();
+}
+
+fn is_cjk(c : char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF // 韩文字母
+        | 0x3040..=0x30FF // 平假名, 片假名
+        | 0x31F0..=0x31FF
+        | 0x3400..=0x4DBF // 汉字扩展A
+        | 0x4E00..=0x9FFF // 汉字
+        | 0xAC00..=0xD7AF // 韩文音节
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2FA1F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ContentType, CustomContent};
+
+    fn item(uuid : &str, text : &str) -> Storage {
+        Storage {
+            owner: String::new(),
+            uuid: uuid.to_string(),
+            content_type: ContentType::Custom("Note".to_string()),
+            content: Contents::Custom(CustomContent {
+                content_type: "Note".to_string(),
+                fields: vec![("text".to_string(), text.to_string())],
+            }),
+            d_platform: String::new(),
+            is_minted: false,
+            mint_time: None,
+            upload_time: 0,
+            edit_time: None,
+        }
+    }
+
+    fn search(index : &SearchIndex, query : &str) -> Vec<String> {
+        index.search(&query_terms(query).unwrap()).into_iter().map(|k| k.uuid).collect()
+    }
+
+    #[test]
+    fn tokenize_words() {
+        assert_eq!(tokenize("Hello, World! rust-2021", false), vec!["hello", "world", "rust", "2021"]);
+        assert_eq!(tokenize("ÄBC", false), vec!["äbc"]);
+        assert!(tokenize(" .,;!? ", false).is_empty());
+        assert_eq!(tokenize(&"a".repeat(MAX_TERM_LEN + 10), false), vec!["a".repeat(MAX_TERM_LEN)]);
+    }
+
+    #[test]
+    fn tokenize_cjk() {
+        assert_eq!(tokenize("你好世界", false), vec!["你", "好", "世", "界", "你好", "好世", "世界"]);
+        assert_eq!(tokenize("你好世界", true), vec!["你好", "好世", "世界"]);
+        assert_eq!(tokenize("好", true), vec!["好"]);
+        assert_eq!(tokenize("ic上的xid", true), vec!["ic", "上的", "xid"]);
+        assert_eq!(tokenize("ic上xid", true), vec!["ic", "上", "xid"]);
+        assert_eq!(tokenize("カタカナ", true), vec!["カタ", "タカ", "カナ"]);
+    }
+
+    #[test]
+    fn query_limits() {
+        assert_eq!(query_terms("Rust rust RUST"), Some(vec!["rust".to_string()]));
+        assert_eq!(query_terms("  ,. "), None);
+        assert_eq!(query_terms(&"a".repeat(MAX_QUERY_LEN + 1)), None);
+        let many : Vec<String> = (0..=MAX_QUERY_TERMS).map(|i| format!("w{}", i)).collect();
+        assert_eq!(query_terms(&many.join(" ")), None);
+        assert_eq!(query_terms(&many[1..].join(" ")).map(|t| t.len()), Some(MAX_QUERY_TERMS));
+    }
+
+    #[test]
+    fn search_prefix_and_intersection() {
+        let mut index = SearchIndex::default();
+        let a = item("a", "Rust on the Internet Computer");
+        let b = item("b", "去中心化身份 rust");
+        index.insert(&a);
+        index.insert(&b);
+
+        assert_eq!(search(&index, "ru"), vec!["a", "b"]);
+        assert_eq!(search(&index, "rust internet"), vec!["a"]);
+        assert_eq!(search(&index, "中心"), vec!["b"]);
+        assert_eq!(search(&index, "身份 rust"), vec!["b"]);
+        assert!(search(&index, "rust python").is_empty());
+
+        index.remove(&a);
+        assert_eq!(search(&index, "rust"), vec!["b"]);
+        assert!(search(&index, "internet").is_empty());
+        index.clear();
+        assert!(search(&index, "rust").is_empty());
+    }
+}
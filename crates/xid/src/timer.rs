@@ -0,0 +1,70 @@
+use std::cell::Cell;
+
+// canister的全局定时器(ic0.global_timer_set)只有一个, 多个任务共用: 记录最近的触发时间,
+// 只有更早的任务才重新设定; 触发后由canister_global_timer执行到期任务并按需重新设定
+
+thread_local! {
+    // 已设定的触发时间, 0表示未设定; 不持久化, 升级会清除定时器, 由post_upgrade重新设定
+    static NEXT : Cell<u64> = const { Cell::new(0) };
+}
+
+#[cfg(target_arch = "wasm32")]
+mod ic0 {
+    #[link(wasm_import_module = "ic0")]
+    extern "C" {
+        pub fn global_timer_set(timestamp : i64) -> i64;
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn set_global_timer(at : u64) {
+    unsafe {
+        ic0::global_timer_set(at.min(i64::MAX as u64) as i64);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn set_global_timer(_at : u64) {}
+
+// at须大于0, 已设定的触发时间不晚于at时不变
+pub fn schedule(at : u64) {
+    let at = at.max(1);
+    NEXT.with(|next| {
+        let current = next.get();
+        if current == 0 || at < current {
+            next.set(at);
+            set_global_timer(at);
+        };
+    });
+}
+
+// 定时器触发后系统已将其清除
+pub fn fired() {
+    NEXT.with(|next| next.set(0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next() -> u64 {
+        NEXT.with(|next| next.get())
+    }
+
+    #[test]
+    fn keeps_earliest_deadline() {
+        fired();
+        schedule(100);
+        assert_eq!(next(), 100);
+        schedule(200);
+        assert_eq!(next(), 100);
+        schedule(50);
+        assert_eq!(next(), 50);
+        fired();
+        schedule(200);
+        assert_eq!(next(), 200);
+        fired();
+        schedule(0);
+        assert_eq!(next(), 1);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::types::XidError;
+
+pub use xid_time::{parse, format_date, format_rfc3339, format_http_date, parse_http_date};
+
+// 校验客户端传入的纳秒时间戳
+pub fn check(t : u64, now : u64) -> Result<(), XidError> {
+    if !xid_time::is_valid(t, now) {
+        return Err(XidError::InvalidTimestamp);
+    };
+    Ok(())
+}
+
+// 推文发布时间可为秒/毫秒/微秒/纳秒, 统一换算为纳秒, 不早于Twitter上线时间
+pub fn normalize_post_time(raw : u64, now : u64) -> Result<u64, XidError> {
+    match xid_time::normalize(raw) {
+        Some(t) if xid_time::is_valid_since(t, xid_time::TWITTER_EPOCH, now) => Ok(t),
+        _ => Err(XidError::InvalidTimestamp),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW : u64 = 1_700_000_000_000_000_000;
+
+    #[test]
+    fn post_time_units_and_floor() {
+        // 2010-01-01T00:00:00Z
+        let t = 1_262_304_000_000_000_000;
+        for raw in [1_262_304_000, 1_262_304_000_000, 1_262_304_000_000_000, t] {
+            assert_eq!(normalize_post_time(raw, NOW).ok(), Some(t));
+        }
+        assert!(normalize_post_time(xid_time::TWITTER_EPOCH, NOW).is_ok());
+        assert!(normalize_post_time(xid_time::TWITTER_EPOCH - 1, NOW).is_err());
+        assert!(normalize_post_time(0, NOW).is_err());
+        assert!(normalize_post_time(NOW + 3_600_000_000_000, NOW).is_err());
+        assert!(normalize_post_time(99_999_999_999, NOW).is_err());
+    }
+}
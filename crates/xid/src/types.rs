@@ -1,8 +1,11 @@
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
+use crate::search::SearchIndex;
+use crate::verify::DelegationIn;
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub enum XidError {
@@ -14,6 +17,29 @@ pub enum XidError {
     FieldOutOfRange,
     XidNotExist,
     XidCNoNameErr,
+    XidExist,
+    TransferExpired,
+    NotGuardian,
+    RecoveryPending,
+    RecoveryNotReady,
+    ContentTypeNotExist,
+    ContentTypeInUse,
+    InvalidContent(String),
+    InvalidTimestamp,
+    InvalidName,
+    CollectionExist,
+    CollectionNotExist,
+    ItemMinted,
+    VersionNotExist,
+    MediaNotExist,
+    UploadNotExist,
+    HashMismatch,
+    CredentialNotExist,
+    IdStale,
+    PresentationNotExist,
+    PresentationExpired,
+    MediaQuotaExceeded,
+    TransferInProgress,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -23,8 +49,26 @@ pub enum XidResponse {
     DeleteOk,
     MintOk,
     ChangeIdOk,
+    CancelOk,
+    DelegateOk,
+    RevokeOk,
+    ProposeOk,
+    TransferOk,
+    ConfigOk,
+    ApproveOk,
+    VetoOk,
+    RegisterOk,
+    TagOk,
+    CollectionOk,
+    UpdateOk,
+    RevertOk,
+    RestoreOk,
+    PurgeOk,
+    UploadOk,
 }
 
+// 变体名与xid center的candid接口一致
+#[allow(non_camel_case_types)]
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub enum XidCenterError {
     Invalid_Operation,
@@ -33,13 +77,14 @@ pub enum XidCenterError {
     XidNotExist,
     IDExist,
     IDNotExist,
+    XidExist,
 }
 
 #[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct ID {
     pub platform : String,
     pub identity : String,
-    pub bind_time : String,
+    pub bind_time : u64, // ns
 }
 impl Eq for ID {}
 impl PartialEq<Self> for ID {
@@ -49,7 +94,7 @@ impl PartialEq<Self> for ID {
 }
 impl PartialOrd<Self> for ID {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some((self.platform.clone() + &self.identity).cmp(&(other.platform.clone() + &other.identity)))
+        Some(self.cmp(other))
     }
 }
 impl Ord for ID {
@@ -65,6 +110,160 @@ pub struct Xid {
     pub main_id : ID,
     pub ids : Vec<ID>,
     pub avatar_url : String,
+    pub statuses : Vec<IdState>,
+}
+
+// W3C DID文档, 方法为did:icp:<canister id>
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DidDocument {
+    pub id : String,
+    pub controller : String,
+    pub also_known_as : Vec<String>,
+    pub verification_method : Vec<VerificationMethod>,
+    pub authentication : Vec<String>,
+    pub assertion_method : Vec<String>,
+    pub service : Vec<DidService>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct VerificationMethod {
+    pub id : String,
+    pub kind : String,
+    pub controller : String,
+    pub blockchain_account_id : Option<String>,
+    pub public_key_multibase : Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DidService {
+    pub id : String,
+    pub kind : String,
+    pub service_endpoint : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ProofMethod {
+    // verify canister校验: keccak256(以太坊签名前缀 + message)的secp256k1签名, 取前64字节
+    Secp256k1Keccak256,
+    // 被绑定principal直接调用verifyIcPost, message为challenge消息, 无签名
+    IcCaller,
+    // II委托链对challenge消息的签名, 委托链见delegation
+    IcDelegation,
+}
+
+// 绑定身份时的原始证明, 供第三方重新校验
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct BindingProof {
+    pub id : SimpleId,
+    pub method : ProofMethod,
+    pub message : ByteBuf,
+    pub signature : ByteBuf,
+    pub attestor_key_id : Option<String>,
+    pub delegation : Option<DelegationIn>,
+    pub verified_at : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum IdStatus {
+    Active,
+    // 超过平台有效期未重新验证
+    Stale,
+    // 已解绑
+    Revoked,
+}
+
+// 身份状态, 平台未设置有效期时expire_time为None
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct IdState {
+    pub id : SimpleId,
+    pub status : IdStatus,
+    pub bind_time : u64,
+    pub expire_time : Option<u64>,
+    pub revoke_time : Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum MainIdUnbind {
+    // 拒绝解绑主身份, 需先changeMainId
+    Refuse,
+    // 按优先级从剩余身份中选出新的主身份
+    Promote,
+}
+
+// 主身份策略; platforms按顺序为自动选择的优先级, 为空时任意平台均可作为主身份;
+// changeMainId变更后cooldown内不能再次变更或解绑, 首次绑定与自动选择不计入;
+// 未设置策略时解绑主身份不选出继任者, 主身份置空
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct MainIdPolicy {
+    pub platforms : Vec<String>,
+    pub on_unbind : MainIdUnbind,
+    pub cooldown : u64, // ns
+}
+
+// 身份可见范围, 未设置时为Public; owner与有IdentityManage权限的代理始终可见
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum IdVisibility {
+    Public,
+    Private,
+    Shared(Vec<Principal>),
+}
+
+// 选择性披露: 指定验证方在有效期内以token读取所选身份及其证明
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Presentation {
+    pub token : String,
+    pub verifier : Principal,
+    pub ids : Vec<SimpleId>,
+    pub create_time : u64,
+    pub expire_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PresentationArgs {
+    pub verifier : Principal,
+    pub ids : Vec<SimpleId>,
+    pub ttl : u64, // ns
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DisclosedId {
+    pub id : ID,
+    pub status : IdStatus,
+    pub proof : Option<BindingProof>,
+}
+
+// 已解绑的身份不再披露
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PresentedIds {
+    pub xid : Principal,
+    pub ids : Vec<DisclosedId>,
+    pub expire_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CredentialFormat {
+    Jwt,
+    JsonLd,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CredentialArgs {
+    pub platform : String,
+    pub identity : String,
+    pub format : CredentialFormat,
+}
+
+// prepareCredential返回待签内容, 原样传给getCredential取回签名后的凭证
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PreparedCredential {
+    pub format : CredentialFormat,
+    pub signing_input : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct IssuedCredential {
+    pub format : CredentialFormat,
+    pub credential : String,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -74,55 +273,248 @@ pub struct StoreArg {
     pub content : Contents,
 }
 
+// 统一的存储记录, content_type标明内容类型
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct Storage {
     pub owner : String,
     pub uuid : String,
+    pub content_type : ContentType,
     pub content : Contents,
     pub d_platform : String,
     pub is_minted : bool,
-    pub mint_time : String,
-    pub upload_time : String,
+    pub mint_time : Option<u64>, // ns, 未铸造时为None
+    pub upload_time : u64, // ns
+    pub edit_time : Option<u64>, // ns, 未修改过时为None
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
-pub struct TwitterStorage {
-    pub owner : String,
-    pub uuid : String,
-    pub twitter_content : TwitterContent,
-    pub d_platform : String,
-    pub is_minted : bool,
-    pub mint_time : String,
-    pub upload_time : String,
+pub struct FieldChange {
+    pub field : String,
+    pub old : Option<String>,
+    pub new : Option<String>,
 }
 
+// 回收站中的内容, 记录删除前所属的标签与合集位置以便恢复
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
-pub struct OffStorage {
-    pub owner : String,
-    pub uuid : String,
-    pub off_content : OffChainContent,
+pub struct TrashEntry {
+    pub item : Storage,
+    pub delete_time : u64,
+    pub expire_time : u64,
+    pub tags : Vec<String>,
+    pub collections : Vec<(String, u64)>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct TrashPage {
+    pub items : Vec<TrashEntry>,
+    pub next_cursor : Option<String>,
+}
+
+// 被替换的历史版本, edit_time为被替换的时间, changes为该次修改的字段差异
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StoreVersion {
+    pub version : u64,
+    pub content : Contents,
     pub d_platform : String,
-    pub is_minted : bool,
-    pub mint_time : String,
-    pub upload_time : String,
+    pub edit_time : u64,
+    pub changes : Vec<FieldChange>,
+}
+
+// 时间区间, 两端均包含, 单位ns
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct TimeRange {
+    pub from : Option<u64>,
+    pub to : Option<u64>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, t : u64) -> bool {
+        self.from.is_none_or(|f| t >= f) && self.to.is_none_or(|e| t <= e)
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StoreFilter {
+    pub is_minted : Option<bool>,
+    pub d_platform : Option<String>,
+    pub uploaded : Option<TimeRange>,
+    pub minted : Option<TimeRange>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ListArgs {
+    pub content_type : ContentType,
+    pub cursor : Option<String>,
+    pub limit : u64,
+    pub filter : StoreFilter,
+    pub order : SortOrder,
+}
+
+// 有序的内容合集, 非公开时仅owner与ContentWrite delegate可见
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Collection {
+    pub name : String,
+    pub description : String,
+    pub is_public : bool,
+    pub items : Vec<ContentUuid>,
+    pub create_time : u64,
+    pub update_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CollectionArgs {
+    pub name : String,
+    pub description : String,
+    pub is_public : bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CollectionInfo {
+    pub name : String,
+    pub description : String,
+    pub is_public : bool,
+    pub size : u64,
+    pub create_time : u64,
+    pub update_time : u64,
+}
+
+impl Collection {
+    pub fn info(&self) -> CollectionInfo {
+        CollectionInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            is_public: self.is_public,
+            size: self.items.len() as u64,
+            create_time: self.create_time,
+            update_time: self.update_time,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct SearchArgs {
+    pub query : String,
+    pub content_type : Option<ContentType>,
+    pub cursor : Option<String>,
+    pub limit : u64,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StorePage {
+    pub items : Vec<Storage>,
+    pub next_cursor : Option<String>,
+}
+
+impl Storage {
+    // 推文按发布时间排序, 缺失时退回上传时间
+    pub fn timeline_key(&self) -> TimelineKey {
+        let time = match &self.content {
+            Contents::TwitterContent(t) if t.post_time > 0 => t.post_time,
+            _ => self.upload_time,
+        };
+        (time, self.content_type.clone(), self.uuid.clone())
+    }
+
+    pub fn key(&self) -> ContentUuid {
+        ContentUuid {
+            content_type: self.content_type.clone(),
+            uuid: self.uuid.clone(),
+        }
+    }
+}
+
+impl StoreFilter {
+    pub fn matches(&self, item : &Storage) -> bool {
+        self.is_minted.is_none_or(|m| m == item.is_minted)
+            && self.d_platform.as_ref().is_none_or(|p| *p == item.d_platform)
+            && self.uploaded.as_ref().is_none_or(|r| r.contains(item.upload_time))
+            && self.minted.as_ref().is_none_or(|r| item.mint_time.is_some_and(|t| r.contains(t)))
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ContentUuid {
     pub content_type: ContentType,
     pub uuid : String,
 }
 
-#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ContentType {
     Twitter,
     OffChain,
+    Custom(String), // owner注册的内容类型
+}
+
+impl ContentType {
+    pub fn name(&self) -> &str {
+        match self {
+            ContentType::Twitter => "Twitter",
+            ContentType::OffChain => "OffChain",
+            ContentType::Custom(name) => name,
+        }
+    }
+
+    pub fn from_name(name : &str) -> ContentType {
+        match name {
+            "Twitter" => ContentType::Twitter,
+            "OffChain" => ContentType::OffChain,
+            _ => ContentType::Custom(name.to_string()),
+        }
+    }
 }
 
+// 时间线索引: (时间, 类型, uuid)
+pub type TimelineKey = (u64, ContentType, String);
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub enum Contents {
     TwitterContent(TwitterContent),
     OffChainContent(OffChainContent),
+    Custom(CustomContent),
+}
+
+impl Contents {
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            Contents::TwitterContent(_) => ContentType::Twitter,
+            Contents::OffChainContent(_) => ContentType::OffChain,
+            Contents::Custom(c) => ContentType::Custom(c.content_type.clone()),
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CustomContent {
+    pub content_type : String,
+    pub fields : Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Url,
+    Number,
+    Timestamp, // 纳秒时间戳
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct FieldSpec {
+    pub name : String,
+    pub kind : FieldKind,
+    pub required : bool,
+    pub max_len : Option<u64>, // 字符数上限
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ContentSchema {
+    pub name : String,
+    pub fields : Vec<FieldSpec>,
 }
 
 #[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -132,7 +524,7 @@ pub struct TwitterContent {
     pub text_url : String,
     pub image_urls : Vec<String>,
     pub video_url : String,
-    pub post_time : String, // 推文发布时间
+    pub post_time : u64, // 推文发布时间, ns
 }
 
 #[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -143,7 +535,7 @@ pub struct OffChainContent {
     pub url : String
 }
 
-#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SimpleId {
     pub platform : String,
     pub identity : String,
@@ -155,6 +547,117 @@ pub struct XidArgs {
     pub avatar_url : Option<String>,
 }
 
+#[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct IcChallenge {
+    pub principal : String,
+    pub nonce : String,
+    pub deadline : u64, // 过期时间 ns
+    pub requester : Option<Principal>, // 发起验证的调用者, 旧数据为None
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Scope {
+    ContentWrite,
+    ProfileWrite,
+    IdentityManage,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Delegate {
+    pub principal : String,
+    pub scopes : Vec<Scope>,
+    pub expiry : Option<u64>, // 过期时间 ns, None为长期有效
+    pub add_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DelegateArgs {
+    pub principal : Principal,
+    pub scopes : Vec<Scope>,
+    pub expiry : Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct OwnerTransfer {
+    pub new_owner : String,
+    pub deadline : u64, // ns
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Guardian {
+    Principal(Principal),
+    Identity(SimpleId), // 须为已绑定的身份
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct RecoveryConfig {
+    pub guardians : Vec<Guardian>,
+    pub threshold : u32,
+    pub window : u64, // 收集审批的时间窗口 ns
+    pub delay : u64, // 达到阈值后owner可否决的时间 ns
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct RecoveryRequest {
+    pub new_owner : String,
+    pub start_time : u64,
+    pub approvals : Vec<Guardian>,
+    pub approved_time : Option<u64>,
+}
+
+// 本地媒体, 按上传时的块存储, 下载时逐块流式返回
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct MediaAsset {
+    pub key : String,
+    pub content_type : String,
+    pub chunks : Vec<ByteBuf>,
+    pub length : u64,
+    pub sha256 : ByteBuf,
+    pub upload_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct MediaInfo {
+    pub key : String,
+    pub content_type : String,
+    pub length : u64,
+    pub sha256 : ByteBuf,
+    pub upload_time : u64,
+}
+
+impl MediaAsset {
+    pub fn info(&self) -> MediaInfo {
+        MediaInfo {
+            key: self.key.clone(),
+            content_type: self.content_type.clone(),
+            length: self.length,
+            sha256: self.sha256.clone(),
+            upload_time: self.upload_time,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct UploadSession {
+    pub key : String,
+    pub content_type : String,
+    pub chunks : BTreeMap<u64, ByteBuf>,
+    pub size : u64,
+    pub expire_time : u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CreateUploadArgs {
+    pub key : String,
+    pub content_type : String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CommitUploadArgs {
+    pub upload_id : u64,
+    pub sha256 : ByteBuf,
+}
+
 #[derive(Default, Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct Avatar {
     pub image_data : Vec<u8>,
@@ -167,11 +670,34 @@ pub struct State {
     pub name : RefCell<String>,
     pub main_id : RefCell<ID>,
     pub ids : RefCell<BTreeSet<ID>>,
-    pub ic_verify : RefCell<String>,
+    pub ic_pending : RefCell<BTreeMap<String, IcChallenge>>,
+    pub delegates : RefCell<BTreeMap<String, Delegate>>,
+    pub pending_owner : RefCell<Option<OwnerTransfer>>,
+    pub transferring : RefCell<bool>, // 不持久化, transferXid调用期间为true
+    pub recovery_config : RefCell<Option<RecoveryConfig>>,
+    pub recovery : RefCell<Option<RecoveryRequest>>,
     pub avatar_url : RefCell<String>,
     pub avatar : RefCell<Avatar>,
-    pub twitter_store : RefCell<BTreeMap<String, TwitterStorage>>,
-    pub off_store : RefCell<BTreeMap<String, OffStorage>>,
+    pub stores : RefCell<BTreeMap<ContentType, BTreeMap<String, Storage>>>,
+    pub schemas : RefCell<BTreeMap<String, ContentSchema>>,
+    pub proofs : RefCell<BTreeMap<SimpleId, BindingProof>>,
+    pub validity : RefCell<BTreeMap<String, u64>>, // 平台 -> 有效期(ns)
+    pub revoked : RefCell<BTreeMap<SimpleId, IdState>>,
+    pub stale_notified : RefCell<BTreeSet<SimpleId>>, // 已通知xid center过期的身份
+    pub visibility : RefCell<BTreeMap<SimpleId, IdVisibility>>,
+    pub presentations : RefCell<BTreeMap<String, Presentation>>,
+    pub main_id_policy : RefCell<Option<MainIdPolicy>>,
+    pub main_id_time : RefCell<u64>, // 主身份最近变更时间
+    pub tags : RefCell<BTreeMap<String, BTreeSet<ContentUuid>>>,
+    pub collections : RefCell<BTreeMap<String, Collection>>,
+    pub versions : RefCell<BTreeMap<ContentUuid, Vec<StoreVersion>>>,
+    pub trash : RefCell<BTreeMap<ContentUuid, TrashEntry>>,
+    pub last_purge : RefCell<u64>, // 不持久化
+    pub media : RefCell<BTreeMap<String, MediaAsset>>,
+    pub upload_sessions : RefCell<BTreeMap<u64, UploadSession>>, // 不持久化, 升级后需重新上传
+    pub next_upload_id : RefCell<u64>,
+    pub timeline : RefCell<BTreeSet<TimelineKey>>, // 不持久化, post_upgrade时重建
+    pub search_index : RefCell<SearchIndex>, // 不持久化, post_upgrade时重建
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -180,9 +706,26 @@ pub struct StableState {
     pub name : String,
     pub main_id : ID,
     pub ids : BTreeSet<ID>,
-    pub ic_verify : String,
+    pub ic_pending : Option<BTreeMap<String, IcChallenge>>,
+    pub delegates : Option<BTreeMap<String, Delegate>>,
+    pub pending_owner : Option<OwnerTransfer>,
+    pub recovery_config : Option<RecoveryConfig>,
+    pub recovery : Option<RecoveryRequest>,
     pub avatar_url : String,
     pub avatar : Avatar,
-    pub twitter_store : BTreeMap<String, TwitterStorage>,
-    pub off_store : BTreeMap<String, OffStorage>,
+    pub stores : Option<BTreeMap<ContentType, BTreeMap<String, Storage>>>,
+    pub schemas : Option<BTreeMap<String, ContentSchema>>,
+    pub proofs : Option<BTreeMap<SimpleId, BindingProof>>,
+    pub validity : Option<BTreeMap<String, u64>>,
+    pub revoked : Option<BTreeMap<SimpleId, IdState>>,
+    pub stale_notified : Option<BTreeSet<SimpleId>>,
+    pub visibility : Option<BTreeMap<SimpleId, IdVisibility>>,
+    pub presentations : Option<BTreeMap<String, Presentation>>,
+    pub main_id_policy : Option<MainIdPolicy>,
+    pub main_id_time : Option<u64>,
+    pub tags : Option<BTreeMap<String, BTreeSet<ContentUuid>>>,
+    pub collections : Option<BTreeMap<String, Collection>>,
+    pub versions : Option<BTreeMap<ContentUuid, Vec<StoreVersion>>>,
+    pub trash : Option<BTreeMap<ContentUuid, TrashEntry>>,
+    pub media : Option<BTreeMap<String, MediaAsset>>,
 }
\ No newline at end of file
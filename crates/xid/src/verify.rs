@@ -1,6 +1,7 @@
-use candid::{CandidType};
+use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
+// verify canister错误的超集, IDNotExist与TooManyChallenges仅由本canister返回
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
 pub enum VerifyError {
     SigDecoErr,
@@ -11,6 +12,13 @@ pub enum VerifyError {
     XidNotExist,
     XidCNoNameErr,
     ReplayErr,
+    NonceErr,
+    ChallengeExpired,
+    DelegationErr,
+    DelegationExpired,
+    TimestampErr,
+    IDNotExist,
+    TooManyChallenges,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
@@ -20,10 +28,15 @@ pub struct Verification {
     pub public_key: Vec<u8>,
 }
 
+// verify canister校验绑定消息所用的secp256k1公钥(压缩格式), 与verify crate中一致
+pub const ATTESTOR_PUBLIC_KEY : [u8; 33] = [2, 142, 36, 253, 150, 84, 241, 44, 121, 61, 61, 55, 108, 21, 247, 171,
+    229, 62, 15, 189, 83, 120, 132, 163, 169, 141, 16, 210, 220, 109, 81, 59, 78];
+pub const ATTESTOR_KEY_ID : &str = "secp256k1:028e24fd9654f12c793d3d376c15f7abe53e0fbd537884a3a98d10d2dc6d513b4e";
+
 #[derive(Serialize, Deserialize, Debug, Clone, CandidType, Default)]
 pub struct Payload {
     pub action : String,  // 行为
-    pub created_at : String, // 创建时间
+    pub created_at : u64, // 创建时间, ns
     pub identity : String, // 地址 推特Id
     pub persona : String, // 待删除
     pub platform : String, // 平台
@@ -34,4 +47,25 @@ pub struct Payload {
 pub struct MsgIn {
     pub msg : String,
     pub sig : String,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct Delegation {
+    pub pubkey : Vec<u8>,
+    pub expiration : u64,
+    pub targets : Option<Vec<Principal>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct SignedDelegation {
+    pub delegation : Delegation,
+    pub signature : Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, CandidType)]
+pub struct DelegationIn {
+    pub public_key : Vec<u8>, // 根身份公钥 (DER)
+    pub delegations : Vec<SignedDelegation>,
+    pub message : Vec<u8>, // 须为ic_challenge_message(nonce)
+    pub signature : Vec<u8>,
+}